@@ -0,0 +1,172 @@
+// wengwengweng
+
+use super::*;
+
+// how far to look for a match before giving up
+const MATCH_LINES: usize = 2000;
+
+pub(super) fn opener_of(ch: char) -> Option<char> {
+	return SCOPE_CHARS
+		.iter()
+		.find(|(_, c)| **c == ch)
+		.map(|(o, _)| *o);
+}
+
+/// A bracket and where its match is, `None` when it doesn't have one.
+pub type BracketMatch = (Cursor, Option<Cursor>);
+
+impl TextEditor {
+
+	// a bracket that counts at a position, not one in a string or comment
+	fn bracket_at(&self, pos: Cursor) -> Option<char> {
+
+		let line = self.get_line_at(pos.line)?;
+		let gi = (pos.col - 1).max(0) as usize;
+		let g = graphemes(&line).nth(gi)?;
+		let mut chars = g.chars();
+
+		let ch = match (chars.next(), chars.next()) {
+			(Some(ch), None) => ch,
+			_ => return None,
+		};
+
+		if !SCOPE_CHARS.contains_key(&ch) && opener_of(ch).is_none() {
+			return None;
+		}
+
+		if self.in_literal((pos.line - 1) as usize, col_to_char(&line, gi)) {
+			return None;
+		}
+
+		return Some(ch);
+
+	}
+
+	/// The bracket at the cursor, or right before it in insert mode, and its match.
+	///
+	/// Brackets in strings and comments are skipped, `None` if there's no bracket or the match is too far to tell.
+	pub(super) fn match_bracket(&self) -> Option<BracketMatch> {
+
+		let mut pos = self.cursor;
+		let mut ch = self.bracket_at(pos);
+
+		if ch.is_none() && self.mode == Mode::Insert && pos.col > 1 {
+			pos.col -= 1;
+			ch = self.bracket_at(pos);
+		}
+
+		let ch = ch?;
+		let forward = SCOPE_CHARS.contains_key(&ch);
+		let first = (pos.line - 1) as usize;
+		let len = self.text.len_lines();
+		// the brackets we're waiting for, innermost last
+		let mut expect = vec![];
+
+		if forward {
+			expect.push(SCOPE_CHARS[&ch]);
+		} else {
+			expect.push(opener_of(ch)?);
+		}
+
+		for n in 0..MATCH_LINES {
+
+			let i = if forward {
+				first + n
+			} else {
+				match first.checked_sub(n) {
+					Some(i) => i,
+					None => break,
+				}
+			};
+
+			if i >= len {
+				break;
+			}
+
+			let line = self.text.line(i)?;
+			let mut gs = vec![];
+			let mut ci = 0;
+
+			for g in graphemes(&line) {
+				gs.push((ci, g));
+				ci += g.chars().count();
+			}
+
+			let cols = (0..gs.len()).filter(|gi| {
+				if i != first {
+					return true;
+				}
+				let cur = (pos.col - 1) as usize;
+				return if forward { *gi > cur } else { *gi < cur };
+			});
+
+			let cols = if forward {
+				cols.collect::<Vec<usize>>()
+			} else {
+				cols.rev().collect::<Vec<usize>>()
+			};
+
+			for gi in cols {
+
+				let (ci, g) = gs[gi];
+				let mut chars = g.chars();
+
+				let c = match (chars.next(), chars.next()) {
+					(Some(c), None) => c,
+					_ => continue,
+				};
+
+				// brackets going the same way open another level, the other way close one
+				let (opens, closes) = if forward {
+					(SCOPE_CHARS.get(&c).copied(), opener_of(c).is_some())
+				} else {
+					(opener_of(c), SCOPE_CHARS.contains_key(&c))
+				};
+
+				if opens.is_none() && !closes {
+					continue;
+				}
+
+				if self.in_literal(i, ci) {
+					continue;
+				}
+
+				if let Some(o) = opens {
+					expect.push(o);
+				} else if expect.last() == Some(&c) {
+					expect.pop();
+					if expect.is_empty() {
+						return Some((pos, Some(Cursor::new(i as Line + 1, gi as Col + 1))));
+					}
+				} else {
+					// closed by the wrong kind
+					return Some((pos, None));
+				}
+
+			}
+
+		}
+
+		if (forward && first + MATCH_LINES < len) || (!forward && first >= MATCH_LINES) {
+			return None;
+		}
+
+		return Some((pos, None));
+
+	}
+
+	/// Move to the bracket matching the one at the cursor.
+	pub(super) fn jump_bracket(&mut self) {
+
+		match self.match_bracket() {
+			Some((_, Some(pos))) => self.move_to(pos),
+			Some((pos, None)) => {
+				let ch = self.bracket_at(pos).unwrap_or(' ');
+				self.log.push(Msg::error(&format!("unbalanced '{}'", ch)));
+			},
+			None => {},
+		}
+
+	}
+
+}
@@ -0,0 +1,187 @@
+// wengwengweng
+
+use super::*;
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+struct CommentTokens {
+	line: Option<&'static str>,
+	block: Option<(&'static str, &'static str)>,
+}
+
+// by syntax name, the bundled syntaxes don't carry comment metadata
+fn comment_tokens(lang: &str) -> Option<CommentTokens> {
+
+	let (line, block) = match lang {
+		"Rust"
+		| "C"
+		| "C++"
+		| "GLSL"
+		| "Go"
+		| "JavaScript"
+		=> (Some("//"), Some(("/*", "*/"))),
+		"CSS" => (None, Some(("/*", "*/"))),
+		"HTML"
+		| "Markdown"
+		=> (None, Some(("<!--", "-->"))),
+		"Python"
+		| "Ruby"
+		| "TOML"
+		| "YAML"
+		| "Makefile"
+		=> (Some("#"), None),
+		"Lua" => (Some("--"), Some(("--[[", "]]"))),
+		"Lisp" => (Some(";"), Some(("#|", "|#"))),
+		_ => return None,
+	};
+
+	return Some(CommentTokens {
+		line: line,
+		block: block,
+	});
+
+}
+
+fn indent_len(line: &str) -> usize {
+	return line.len() - line.trim_start_matches(|c| c == ' ' || c == '\t').len();
+}
+
+impl TextEditor {
+
+	fn comment_tokens(&mut self) -> Option<CommentTokens> {
+
+		let tokens = self.lang.as_ref().and_then(|l| comment_tokens(l));
+
+		if tokens.is_none() {
+			self.log.push(Msg::error("no comment syntax for this file"));
+		}
+
+		return tokens;
+
+	}
+
+	/// Toggle line comments on lines `l1` to `l2`, inserted after the common indentation.
+	///
+	/// Languages without line comments get every line wrapped in a block comment instead.
+	pub(super) fn toggle_comment_lines(&mut self, l1: Line, l2: Line) {
+
+		let tokens = match self.comment_tokens() {
+			Some(t) => t,
+			None => return,
+		};
+
+		let (start, end) = match (tokens.line, tokens.block) {
+			(Some(line), _) => (line, None),
+			(None, Some((start, end))) => (start, Some(end)),
+			(None, None) => return,
+		};
+
+		let lines = (l1..=l2)
+			.filter_map(|ln| self.get_line_at(ln).map(|l| (ln, l)))
+			.filter(|(_, l)| !l.trim().is_empty())
+			.collect::<Vec<(Line, String)>>();
+
+		if lines.is_empty() {
+			return;
+		}
+
+		let commented = lines
+			.iter()
+			.all(|(_, l)| {
+				let body = &l[indent_len(l)..];
+				return body.starts_with(start) && end.map(|e| body.trim_end().ends_with(e)).unwrap_or(true);
+			});
+
+		let indent = lines
+			.iter()
+			.map(|(_, l)| indent_len(l))
+			.min()
+			.unwrap_or(0);
+
+		for (ln, line) in lines {
+
+			let new_line = if commented {
+
+				let i = indent_len(&line);
+				let mut body = &line[i + start.len()..];
+
+				if let Some(end) = end {
+					body = body.trim_end();
+					body = body.strip_suffix(end).unwrap_or(body);
+					body = body.strip_suffix(' ').unwrap_or(body);
+				}
+
+				format!("{}{}", &line[..i], body.strip_prefix(' ').unwrap_or(body))
+
+			} else {
+
+				let (ind, body) = line.split_at(indent);
+
+				match end {
+					Some(end) => format!("{}{} {} {}", ind, start, body, end),
+					None => format!("{}{} {}", ind, start, body),
+				}
+
+			};
+
+			self.set_line_at(ln, &new_line);
+
+		}
+
+		self.highlight();
+
+	}
+
+	pub(super) fn toggle_comment(&mut self) {
+		self.toggle_comment_lines(self.cursor.line, self.cursor.line);
+	}
+
+	/// Toggle a block comment around the span from `start` to `end` inclusive, or around whole lines when `lines` is set.
+	pub(super) fn toggle_block_comment(&mut self, start: Cursor, end: Cursor, lines: bool) {
+
+		let tokens = match self.comment_tokens() {
+			Some(t) => t,
+			None => return,
+		};
+
+		let (bstart, bend) = match tokens.block {
+			Some(b) => b,
+			None => {
+				self.log.push(Msg::error("no block comments for this file"));
+				return;
+			},
+		};
+
+		// whole lines go from after the indentation to the line end
+		let (start, end) = if lines {
+			let first = self.get_line_at(start.line).unwrap_or(String::new());
+			let indent = graphemes(&first[..indent_len(&first)]).count() as Col;
+			let len = self.line_len_at(end.line).unwrap_or(0);
+			(Cursor::new(start.line, indent + 1), Cursor::new(end.line, len))
+		} else {
+			(start, end)
+		};
+
+		let s = self.pos_to_idx(start);
+		let e = self.pos_to_idx(Cursor::new(end.line, end.col + 1));
+		let text = self.text.slice(s, e);
+
+		if text.starts_with(bstart) && text.ends_with(bend) && text.len() >= bstart.len() + bend.len() {
+
+			let inner = &text[bstart.len()..text.len() - bend.len()];
+			let inner = inner.strip_prefix(' ').unwrap_or(inner);
+			let inner = inner.strip_suffix(' ').unwrap_or(inner);
+
+			self.text.remove(s, e);
+			self.text.insert(s, inner);
+
+		} else {
+			self.text.insert(e, &format!(" {}", bend));
+			self.text.insert(s, &format!("{} ", bstart));
+		}
+
+		self.move_to(start);
+		self.highlight();
+
+	}
+
+}
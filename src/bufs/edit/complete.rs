@@ -0,0 +1,608 @@
+// wengwengweng
+
+use std::time::Instant;
+use std::time::Duration;
+use std::collections::BTreeMap;
+
+use super::*;
+
+// items shown at once
+const POPUP_ITEMS: usize = 8;
+// most words offered
+const MAX_WORDS: usize = 100;
+// how far words in other buffers count as being, in lines
+const OTHER_BUF_DIST: usize = 1000;
+// letters typed before completing on its own
+const AUTO_MIN_PREFIX: usize = 2;
+// how often a buffer's words are published again after edits
+const WORDS_INTERVAL: Duration = Duration::from_secs(2);
+// lines around the cursor looked through for how close words are, the rest count as this far
+const NEAR_LINES: usize = 500;
+
+/// Something that can be inserted at the cursor.
+#[derive(Clone, Debug, PartialEq)]
+pub struct CompleteItem {
+	pub label: String,
+	pub detail: Option<String>,
+	pub insert: String,
+	// what it replaces, the word before the cursor if none
+	pub replace: Option<(Cursor, Cursor)>,
+}
+
+/// Completion popup, narrowed down by what's typed after it opened.
+#[derive(Clone, Debug)]
+pub struct Completion {
+	items: Vec<CompleteItem>,
+	// indices of the items that match
+	shown: Vec<usize>,
+	selected: usize,
+	// start of the word being completed
+	start: Cursor,
+}
+
+/// Words in each open buffer and how many times they're there.
+#[derive(Clone, Debug, Default)]
+pub struct WordIndex {
+	bufs: HashMap<PathBuf, BTreeMap<String, usize>>,
+	// counts of every buffer added up, kept as they're published so completing only looks up a prefix
+	all: BTreeMap<String, usize>,
+}
+
+impl WordIndex {
+
+	pub fn new() -> Self {
+		return Self::default();
+	}
+
+	fn set(&mut self, path: &Path, words: BTreeMap<String, usize>) {
+		self.remove(path);
+		for (w, n) in &words {
+			*self.all.entry(w.clone()).or_insert(0) += n;
+		}
+		self.bufs.insert(path.to_path_buf(), words);
+	}
+
+	fn remove(&mut self, path: &Path) {
+		if let Some(words) = self.bufs.remove(path) {
+			for (w, n) in words {
+				if let Some(c) = self.all.get_mut(&w) {
+					*c -= n;
+					if *c == 0 {
+						self.all.remove(&w);
+					}
+				}
+			}
+		}
+	}
+
+	// words starting with a prefix in every buffer but one, counts added up
+	fn others(&self, path: &Path, prefix: &str) -> Vec<(String, usize)> {
+
+		let own = self.bufs.get(path);
+
+		return with_prefix(&self.all, prefix)
+			.filter_map(|(w, n)| {
+				let n = n - own.and_then(|o| o.get(w)).unwrap_or(&0);
+				return if n > 0 { Some((w.clone(), n)) } else { None };
+			})
+			.collect();
+
+	}
+
+}
+
+fn is_word_char(ch: char) -> bool {
+	return ch.is_alphanumeric() || ch == '_';
+}
+
+// the words in a line, with the char index they start at
+fn line_words(line: &str) -> Vec<(usize, &str)> {
+
+	let mut words = vec![];
+	let mut start = None;
+
+	for (ci, (bi, ch)) in line.char_indices().enumerate() {
+		match (is_word_char(ch), start) {
+			(true, None) => start = Some((ci, bi)),
+			(false, Some((sc, sb))) => {
+				words.push((sc, &line[sb..bi]));
+				start = None;
+			},
+			_ => {},
+		}
+	}
+
+	if let Some((sc, sb)) = start {
+		words.push((sc, &line[sb..]));
+	}
+
+	return words;
+
+}
+
+// words that start with a prefix and are longer than it
+fn with_prefix<'a>(words: &'a BTreeMap<String, usize>, prefix: &'a str) -> impl Iterator<Item = (&'a String, &'a usize)> {
+	return words
+		.range(prefix.to_string()..)
+		.take_while(move |(w, _)| w.starts_with(prefix))
+		.filter(move |(w, _)| w.as_str() != prefix);
+}
+
+// count the words in a line in, or out
+fn add_words(counts: &mut BTreeMap<String, usize>, line: &str, add: bool) {
+	for (_, w) in line_words(line) {
+		if add {
+			*counts.entry(w.to_string()).or_insert(0) += 1;
+		} else if let Some(n) = counts.get_mut(w) {
+			*n -= 1;
+			if *n == 0 {
+				counts.remove(w);
+			}
+		}
+	}
+}
+
+impl TextEditor {
+
+	/// Start of the word the cursor is at the end of.
+	pub(super) fn word_start(&self) -> Cursor {
+
+		let line = self.cur_line().unwrap_or(String::new());
+		let col = (self.cursor.col - 1).max(0) as usize;
+
+		let len = graphemes(&line)
+			.take(col)
+			.collect::<Vec<&str>>()
+			.iter()
+			.rev()
+			.take_while(|g| g.chars().all(is_word_char))
+			.count();
+
+		return Cursor::new(self.cursor.line, self.cursor.col - len as Col);
+
+	}
+
+	// what's typed from the start of the word to the cursor
+	pub(super) fn completion_prefix(&self, start: Cursor) -> String {
+		let line = self.cur_line().unwrap_or(String::new());
+		return graphemes(&line)
+			.skip((start.col - 1).max(0) as usize)
+			.take((self.cursor.col - start.col).max(0) as usize)
+			.collect();
+	}
+
+	/// Count all the words again, when everything's rendered again.
+	pub(super) fn count_words(&mut self) {
+
+		let mut counts = bmap![];
+
+		for line in self.text.lines() {
+			add_words(&mut counts, &line, true);
+		}
+
+		self.word_counts = counts;
+
+	}
+
+	/// Let other buffers complete with the words in this one.
+	pub(super) fn publish_words(&mut self) {
+		self.words_dirty = None;
+		if self.scratch {
+			return;
+		}
+		self.words.borrow_mut().set(&self.path, self.word_counts.clone());
+	}
+
+	pub(super) fn unpublish_words(&mut self) {
+		self.words.borrow_mut().remove(&self.path);
+	}
+
+	/// Count lines `first..old_end` out and `first..new_end` in, the old ones are still as they were rendered.
+	pub(super) fn words_changed(&mut self, first: usize, old_end: usize, new_end: usize) {
+
+		for l in &self.rendered_lines[first..old_end] {
+			let line = l.iter().map(|c| c.text.as_str()).collect::<String>();
+			add_words(&mut self.word_counts, &line, false);
+		}
+
+		for i in first..new_end {
+			if let Some(line) = self.text.line(i) {
+				add_words(&mut self.word_counts, &line, true);
+			}
+		}
+
+		if self.words_dirty.is_none() {
+			self.words_dirty = Some(Instant::now());
+		}
+
+	}
+
+	/// Publish the words again a while after edits, and complete after a pause in typing.
+	pub(super) fn update_completion_sources(&mut self) {
+
+		if self.words_dirty.map(|t| t.elapsed() >= WORDS_INTERVAL).unwrap_or(false) {
+			self.publish_words();
+		}
+
+		let typed = match self.typed {
+			Some(t) => t,
+			None => return,
+		};
+
+		let delay = match self.complete_delay {
+			Some(d) => d,
+			None => return,
+		};
+
+		if self.mode != Mode::Insert || self.completion.is_some() {
+			self.typed = None;
+			return;
+		}
+
+		if typed.elapsed() < delay {
+			return;
+		}
+
+		self.typed = None;
+
+		let prefix = self.completion_prefix(self.word_start());
+
+		if graphemes(&prefix).count() >= AUTO_MIN_PREFIX || self.path_at_cursor().is_some() {
+			self.complete(false);
+		}
+
+	}
+
+	/// Complete what's before the cursor, a path in a string, or with the language server, or words in open buffers.
+	///
+	/// `manual` is when asked for, which says so when there's nothing and inserts right away when there's one.
+	pub(super) fn complete(&mut self, manual: bool) {
+
+		if self.complete_paths(manual) {
+			return;
+		}
+
+		if self.has_lsp() {
+			self.request_completion();
+			return;
+		}
+
+		self.complete_words(manual);
+
+	}
+
+	/// Words in this buffer closest to the cursor and most used first, then other buffers.
+	pub(super) fn complete_words(&mut self, manual: bool) {
+
+		let start = self.word_start();
+		let prefix = self.completion_prefix(start);
+
+		if prefix.is_empty() {
+			if manual {
+				self.log.push(Msg::info("no completions"));
+			}
+			return;
+		}
+
+		let cur = (self.cursor.line - 1).max(0) as usize;
+		// closest line and count of each word
+		let mut found: HashMap<String, (usize, usize)> = with_prefix(&self.word_counts, &prefix)
+			.map(|(w, n)| (w.clone(), (NEAR_LINES, *n)))
+			.collect();
+		let mut left = found.len();
+
+		// out from the cursor until every word's been seen, only splitting lines that have the prefix
+		for d in 0..NEAR_LINES {
+
+			if left == 0 {
+				break;
+			}
+
+			for i in [cur.checked_sub(d), Some(cur + d).filter(|_| d > 0)].iter().flatten() {
+
+				let line = match self.text.line(*i) {
+					Some(l) if l.contains(prefix.as_str()) => l,
+					_ => continue,
+				};
+
+				for (_, w) in line_words(&line) {
+					if let Some(e) = found.get_mut(w) {
+						if d < e.0 {
+							e.0 = d;
+							left -= 1;
+						}
+					}
+				}
+
+			}
+
+		}
+
+		for (w, n) in self.words.borrow().others(&self.path, &prefix) {
+			found.entry(w).or_insert((OTHER_BUF_DIST, 0)).1 += n;
+		}
+
+		let mut words = found.into_iter().collect::<Vec<(String, (usize, usize))>>();
+
+		let score = |(dist, n): (usize, usize)| n as f32 / (1.0 + dist as f32);
+
+		words.sort_by(|(w1, s1), (w2, s2)| {
+			return score(*s2)
+				.partial_cmp(&score(*s1))
+				.unwrap_or(std::cmp::Ordering::Equal)
+				.then(w1.cmp(w2));
+		});
+
+		let items = words
+			.into_iter()
+			.take(MAX_WORDS)
+			.map(|(w, _)| {
+				return CompleteItem {
+					label: w.clone(),
+					detail: None,
+					insert: w,
+					replace: None,
+				};
+			})
+			.collect();
+
+		if !self.show_completion(start, items, manual) && manual {
+			self.log.push(Msg::info("no completions"));
+		}
+
+	}
+
+	// a path typed in a string up to the cursor
+	fn path_at_cursor(&self) -> Option<String> {
+
+		let line = self.cur_line()?;
+		let col = (self.cursor.col - 1).max(0) as usize;
+		let before = graphemes(&line).take(col).collect::<Vec<&str>>();
+		let q = before.iter().rposition(|g| *g == "\"" || *g == "'")?;
+		let quote = before[q];
+
+		// an odd number of quotes before means we're in the string
+		if before.iter().filter(|g| **g == quote).count() % 2 == 0 {
+			return None;
+		}
+
+		let text = before[q + 1..].concat();
+
+		if !text.contains('/') && !text.starts_with('.') && !text.starts_with('~') {
+			return None;
+		}
+
+		return Some(text);
+
+	}
+
+	// entries of the dir typed so far, false if the cursor isn't in a path
+	fn complete_paths(&mut self, manual: bool) -> bool {
+
+		let text = match self.path_at_cursor() {
+			Some(text) => text,
+			None => return false,
+		};
+
+		let (dir, name) = match text.rfind('/') {
+			Some(i) => (&text[..=i], &text[i + 1..]),
+			None => ("./", &text[..]),
+		};
+
+		let dir = expand_path(dir);
+
+		let dir = if dir.is_absolute() {
+			dir
+		} else {
+			self.path
+				.parent()
+				.map(|p| p.join(&dir))
+				.unwrap_or(dir)
+		};
+
+		let start = Cursor::new(self.cursor.line, self.cursor.col - graphemes(name).count() as Col);
+
+		let mut entries = std::fs::read_dir(&dir)
+			.map(|entries| {
+				return entries
+					.filter_map(|e| e.ok())
+					.filter_map(|e| {
+						let fname = e.file_name().to_string_lossy().to_string();
+						// hidden ones only when asked for
+						if !fname.starts_with(name) || (fname.starts_with('.') && !name.starts_with('.')) {
+							return None;
+						}
+						let is_dir = e.file_type().map(|t| t.is_dir()).unwrap_or(false);
+						return Some(if is_dir { format!("{}/", fname) } else { fname });
+					})
+					.collect::<Vec<String>>();
+			})
+			.unwrap_or(vec![]);
+
+		entries.sort();
+
+		let items = entries
+			.into_iter()
+			.map(|e| {
+				return CompleteItem {
+					label: e.clone(),
+					detail: None,
+					insert: e,
+					replace: Some((start, self.cursor)),
+				};
+			})
+			.collect();
+
+		if !self.show_completion(start, items, manual) && manual {
+			self.log.push(Msg::info("no completions"));
+		}
+
+		return true;
+
+	}
+
+	/// Open the popup with some items, `insert_single` just inserts when there's only one.
+	///
+	/// `start` is where what's typed to narrow the items down starts, returns false if there's nothing to show.
+	pub(super) fn show_completion(&mut self, start: Cursor, items: Vec<CompleteItem>, insert_single: bool) -> bool {
+
+		if self.mode != Mode::Insert {
+			return false;
+		}
+
+		let mut c = Completion {
+			items: items,
+			shown: vec![],
+			selected: 0,
+			start: start,
+		};
+
+		self.filter_completion(&mut c);
+
+		match c.shown.len() {
+			0 => return false,
+			1 if insert_single => {
+				let item = c.items[c.shown[0]].clone();
+				self.accept_item(c.start, item);
+			},
+			_ => self.completion = Some(c),
+		}
+
+		return true;
+
+	}
+
+	fn filter_completion(&self, c: &mut Completion) {
+
+		let prefix = self.completion_prefix(c.start).to_lowercase();
+
+		let (mut starts, contains): (Vec<usize>, Vec<usize>) = (0..c.items.len())
+			.filter(|i| c.items[*i].label.to_lowercase().contains(&prefix))
+			.partition(|i| c.items[*i].label.to_lowercase().starts_with(&prefix));
+
+		starts.extend(contains);
+		c.shown = starts;
+		c.selected = c.selected.min(c.shown.len().saturating_sub(1));
+
+	}
+
+	/// Narrow down the popup as things are typed, and close it when the cursor left the word.
+	pub(super) fn update_completion(&mut self) {
+
+		let mut c = match self.completion.take() {
+			Some(c) => c,
+			None => return,
+		};
+
+		if self.mode != Mode::Insert || self.cursor.line != c.start.line || self.cursor.col < c.start.col {
+			return;
+		}
+
+		self.filter_completion(&mut c);
+
+		if !c.shown.is_empty() {
+			self.completion = Some(c);
+		}
+
+	}
+
+	pub(super) fn select_completion(&mut self, down: bool) {
+		if let Some(c) = &mut self.completion {
+			let len = c.shown.len();
+			c.selected = if down { (c.selected + 1) % len } else { (c.selected + len - 1) % len };
+		}
+	}
+
+	pub(super) fn accept_completion(&mut self) {
+
+		let c = match self.completion.take() {
+			Some(c) => c,
+			None => return,
+		};
+
+		if let Some(i) = c.shown.get(c.selected) {
+			self.accept_item(c.start, c.items[*i].clone());
+		}
+
+	}
+
+	fn accept_item(&mut self, start: Cursor, item: CompleteItem) {
+
+		// what's typed since the request replaces as well
+		let (s, e) = match item.replace {
+			Some((s, e)) if s.line == self.cursor.line => (s, e.max(self.cursor)),
+			_ => (start, self.cursor),
+		};
+
+		let (si, ei) = (self.pos_to_idx(s), self.pos_to_idx(e));
+
+		self.text.remove(si, ei);
+		self.text.insert(si, &item.insert);
+		self.move_to(self.idx_to_pos(si + item.insert.chars().count()));
+		self.highlight();
+
+	}
+
+	/// Items around the cursor, and where the popup goes.
+	pub(super) fn draw_completion(&self, gfx: &mut Gfx, x: f32, y: f32) -> Result<()> {
+
+		let c = match &self.completion {
+			Some(c) => c,
+			None => return Ok(()),
+		};
+
+		let first = c.selected.saturating_sub(POPUP_ITEMS - 1);
+		let shown = &c.shown[first..(first + POPUP_ITEMS).min(c.shown.len())];
+
+		let lines = shown
+			.iter()
+			.map(|i| {
+				let item = &c.items[*i];
+				return match &item.detail {
+					Some(d) => format!("{}  {}", item.label, d),
+					None => item.label.clone(),
+				};
+			})
+			.collect::<Vec<String>>();
+
+		let w = lines.iter().map(|l| width(l)).max().unwrap_or(0) as f32 * self.wrap.cell + LINE_SPACING * 2.0;
+		let h = LINE_HEIGHT * lines.len() as f32;
+
+		gfx.draw(
+			&shapes::rect(
+				vec2!(x, -y),
+				vec2!(x + w, -y - h),
+			)
+				.fill(rgba!(0.1, 0.1, 0.15, 0.95))
+		)?;
+
+		for (n, l) in lines.iter().enumerate() {
+
+			let ly = y + n as f32 * LINE_HEIGHT;
+
+			if first + n == c.selected {
+				gfx.draw(
+					&shapes::rect(
+						vec2!(x, -ly),
+						vec2!(x + w, -ly - LINE_HEIGHT),
+					)
+						.fill(rgba!(1, 1, 1, 0.2))
+				)?;
+			}
+
+			gfx.draw_t(
+				mat4!()
+					.t2(vec2!(x + LINE_SPACING, -ly - LINE_SPACING * 0.5))
+					,
+				&shapes::text(l)
+					.align(gfx::Origin::TopLeft)
+					.size(FONT_SIZE)
+			)?;
+
+		}
+
+		return Ok(());
+
+	}
+
+}
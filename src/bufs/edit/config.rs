@@ -0,0 +1,564 @@
+// wengwengweng
+
+use std::collections::HashMap;
+use regex::Regex;
+
+use super::*;
+
+const EDITORCONFIG: &str = ".editorconfig";
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum IndentStyle {
+	Tab,
+	Space,
+}
+
+// a lone '\r' isn't split on when loading, so it's not offered for saving either
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum LineEnding {
+	Lf,
+	Crlf,
+}
+
+impl LineEnding {
+
+	pub fn from_name(name: &str) -> Option<Self> {
+		return match name {
+			"lf" => Some(LineEnding::Lf),
+			"crlf" => Some(LineEnding::Crlf),
+			_ => None,
+		};
+	}
+
+	pub fn name(&self) -> &'static str {
+		return match self {
+			LineEnding::Lf => "lf",
+			LineEnding::Crlf => "crlf",
+		};
+	}
+
+	pub fn as_str(&self) -> &'static str {
+		return match self {
+			LineEnding::Lf => "\n",
+			LineEnding::Crlf => "\r\n",
+		};
+	}
+
+}
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum LineNumbers {
+	Off,
+	Absolute,
+	// from the cursor line, which has its own
+	Relative,
+}
+
+impl LineNumbers {
+	pub fn from_name(name: &str) -> Option<Self> {
+		return match name {
+			"off" => Some(LineNumbers::Off),
+			"absolute" => Some(LineNumbers::Absolute),
+			"relative" => Some(LineNumbers::Relative),
+			_ => None,
+		};
+	}
+}
+
+/// How a file is indented, read and written.
+///
+/// `None` means keep what the file had when it was loaded, the editor fills those in on load.
+#[derive(Clone, Debug, PartialEq)]
+pub struct FileConf {
+	pub indent_style: IndentStyle,
+	pub indent_size: usize,
+	pub tab_width: usize,
+	pub trim_trailing_whitespace: bool,
+	pub insert_final_newline: Option<bool>,
+	pub end_of_line: Option<LineEnding>,
+	pub charset: Option<Charset>,
+	pub soft_wrap: bool,
+	// wrap at a column instead of the view width
+	pub wrap_column: Option<usize>,
+	// indent continuation rows like the line they belong to
+	pub wrap_indent: bool,
+	pub line_numbers: LineNumbers,
+	// the read and write settings before the file filled them in
+	configured: Format,
+}
+
+// what's None is filled in from the file every time it's loaded
+#[derive(Clone, Copy, Debug, Default, PartialEq)]
+struct Format {
+	insert_final_newline: Option<bool>,
+	end_of_line: Option<LineEnding>,
+	charset: Option<Charset>,
+}
+
+impl Default for FileConf {
+	fn default() -> Self {
+		return Self {
+			indent_style: IndentStyle::Tab,
+			indent_size: TAB_WIDTH,
+			tab_width: TAB_WIDTH,
+			trim_trailing_whitespace: true,
+			insert_final_newline: None,
+			end_of_line: None,
+			charset: None,
+			soft_wrap: false,
+			wrap_column: None,
+			wrap_indent: true,
+			line_numbers: LineNumbers::Absolute,
+			configured: Format::default(),
+		};
+	}
+}
+
+// properties as they're set, before being resolved against each other
+#[derive(Clone, Debug, Default)]
+struct Props {
+	indent_style: Option<IndentStyle>,
+	// None inside means "tab", same as tab width
+	indent_size: Option<Option<usize>>,
+	tab_width: Option<usize>,
+	trim_trailing_whitespace: Option<bool>,
+	insert_final_newline: Option<bool>,
+	end_of_line: Option<LineEnding>,
+	charset: Option<Charset>,
+	soft_wrap: Option<bool>,
+	wrap_column: Option<usize>,
+	wrap_indent: Option<bool>,
+	line_numbers: Option<LineNumbers>,
+}
+
+impl Props {
+
+	// unknown keys and values are ignored, "unset" goes back to the default
+	fn set(&mut self, key: &str, val: &str) {
+
+		let val = val.trim().to_lowercase();
+		let unset = val == "unset";
+
+		match key.trim().to_lowercase().as_str() {
+			"indent_style" => {
+				self.indent_style = match val.as_str() {
+					"tab" => Some(IndentStyle::Tab),
+					"space" => Some(IndentStyle::Space),
+					_ => if unset { None } else { self.indent_style },
+				};
+			},
+			"indent_size" => {
+				self.indent_size = if val == "tab" {
+					Some(None)
+				} else if unset {
+					None
+				} else {
+					val.parse().ok().filter(|n| *n > 0).map(Some).or(self.indent_size)
+				};
+			},
+			"tab_width" => {
+				self.tab_width = if unset {
+					None
+				} else {
+					val.parse().ok().filter(|n| *n > 0).or(self.tab_width)
+				};
+			},
+			"trim_trailing_whitespace" => {
+				self.trim_trailing_whitespace = if unset { None } else { val.parse().ok().or(self.trim_trailing_whitespace) };
+			},
+			"insert_final_newline" => {
+				self.insert_final_newline = if unset { None } else { val.parse().ok().or(self.insert_final_newline) };
+			},
+			"end_of_line" => {
+				self.end_of_line = match LineEnding::from_name(&val) {
+					Some(eol) => Some(eol),
+					None => if unset { None } else { self.end_of_line },
+				};
+			},
+			"charset" => {
+				self.charset = match Charset::from_name(&val) {
+					Some(charset) => Some(charset),
+					None => if unset { None } else { self.charset },
+				};
+			},
+			// not editorconfig, but set the same way
+			"soft_wrap" => {
+				self.soft_wrap = if unset { None } else { val.parse().ok().or(self.soft_wrap) };
+			},
+			"wrap_column" => {
+				self.wrap_column = if unset {
+					None
+				} else {
+					val.parse().ok().filter(|n| *n > 0).or(self.wrap_column)
+				};
+			},
+			"wrap_indent" => {
+				self.wrap_indent = if unset { None } else { val.parse().ok().or(self.wrap_indent) };
+			},
+			"line_numbers" => {
+				self.line_numbers = match LineNumbers::from_name(&val) {
+					Some(n) => Some(n),
+					None => if unset { None } else { self.line_numbers },
+				};
+			},
+			_ => {},
+		}
+
+	}
+
+	fn resolve(self) -> FileConf {
+
+		let def = FileConf::default();
+
+		let indent_size = match self.indent_size {
+			Some(Some(n)) => Some(n),
+			Some(None) => self.tab_width,
+			None => None,
+		};
+
+		let tab_width = self.tab_width.or(indent_size).unwrap_or(def.tab_width);
+
+		return FileConf {
+			indent_style: self.indent_style.unwrap_or(def.indent_style),
+			indent_size: indent_size.unwrap_or(tab_width),
+			tab_width: tab_width,
+			trim_trailing_whitespace: self.trim_trailing_whitespace.unwrap_or(def.trim_trailing_whitespace),
+			insert_final_newline: self.insert_final_newline,
+			end_of_line: self.end_of_line,
+			charset: self.charset,
+			soft_wrap: self.soft_wrap.unwrap_or(def.soft_wrap),
+			wrap_column: self.wrap_column,
+			wrap_indent: self.wrap_indent.unwrap_or(def.wrap_indent),
+			line_numbers: self.line_numbers.unwrap_or(def.line_numbers),
+			configured: Format {
+				insert_final_newline: self.insert_final_newline,
+				end_of_line: self.end_of_line,
+				charset: self.charset,
+			},
+		};
+
+	}
+
+}
+
+impl FileConf {
+
+	/// Settings for a file, from the per-language overrides in [`Conf`] and then `.editorconfig` files up the directory tree.
+	pub fn for_file(path: impl AsRef<Path>, lang: Option<&str>, langs: &HashMap<String, HashMap<String, String>>) -> Self {
+
+		let path = path.as_ref();
+		let path = std::fs::canonicalize(path).unwrap_or(path.to_path_buf());
+		let mut props = Props::default();
+
+		if let Some(conf) = lang.and_then(|l| langs.get(l)) {
+			for (k, v) in conf {
+				props.set(k, v);
+			}
+		}
+
+		for (k, v) in editorconfig(&path) {
+			props.set(&k, &v);
+		}
+
+		return props.resolve();
+
+	}
+
+	/// One level of indentation.
+	pub fn indent_unit(&self) -> String {
+		return match self.indent_style {
+			IndentStyle::Tab => String::from("\t"),
+			IndentStyle::Space => " ".repeat(self.indent_size),
+		};
+	}
+
+	/// Set how the file's written, instead of what it had when loaded, kept on reload. `None` detects it again.
+	pub fn set_charset(&mut self, charset: Option<Charset>) {
+		self.charset = charset;
+		self.configured.charset = charset;
+	}
+
+	pub fn set_end_of_line(&mut self, eol: LineEnding) {
+		self.end_of_line = Some(eol);
+		self.configured.end_of_line = Some(eol);
+	}
+
+	pub fn set_final_newline(&mut self, b: bool) {
+		self.insert_final_newline = Some(b);
+		self.configured.insert_final_newline = Some(b);
+	}
+
+	/// Decode file content into text with '\n' line endings and no final newline, filling in what's not configured with what the file has.
+	///
+	/// What was filled in from an earlier load is detected again, so a reload follows what changed on disk.
+	///
+	/// A configured charset the content isn't valid in would lose data, so it's guessed instead, with a warning. Mixed line endings are warned about too, since they'll all be saved as one.
+	pub fn decode_file(&mut self, bytes: &[u8]) -> (String, Vec<String>) {
+
+		let mut warnings = vec![];
+
+		self.charset = self.configured.charset;
+		self.end_of_line = self.configured.end_of_line;
+		self.insert_final_newline = self.configured.insert_final_newline;
+
+		let charset = match self.charset {
+			Some(c) if decode(bytes, c).is_some() => c,
+			Some(c) => {
+				let detected = detect(bytes);
+				warnings.push(format!("not valid {}, opened as {}", c.name(), detected.name()));
+				detected
+			},
+			None => detect(bytes),
+		};
+
+		let content = decode(bytes, charset).unwrap_or(String::new());
+		let eol = detect_line_ending(&content);
+		let mixed = mixed_line_endings(&content);
+		let mut content = normalize_line_endings(&content);
+
+		// new files get a final newline
+		self.charset = Some(charset);
+		self.end_of_line = self.end_of_line.or(eol).or(Some(LineEnding::Lf));
+
+		if mixed {
+			warnings.push(format!("mixed line endings, will be saved as {}", self.end_of_line.unwrap_or(LineEnding::Lf).name()));
+		}
+		self.insert_final_newline = self.insert_final_newline.or(Some(bytes.is_empty() || content.ends_with('\n')));
+
+		if content.ends_with('\n') {
+			content.pop();
+		}
+
+		return (content, warnings);
+
+	}
+
+	/// How the file is written to disk, like "crlf utf-8-bom noeol", plain utf-8 isn't mentioned.
+	pub fn format_label(&self) -> String {
+
+		let mut label = self.end_of_line.unwrap_or(LineEnding::Lf).name().to_string();
+
+		if let Some(charset) = self.charset.filter(|c| *c != Charset::Utf8) {
+			label.push(' ');
+			label.push_str(charset.name());
+		}
+
+		if self.insert_final_newline == Some(false) {
+			label.push_str(" noeol");
+		}
+
+		return label;
+
+	}
+
+	/// How many levels a line is indented, tabs count up to the next tab stop.
+	pub fn indent_level(&self, line: &str) -> usize {
+
+		let mut width = 0;
+
+		for ch in line.chars() {
+			match ch {
+				'\t' => width += self.tab_width - width % self.tab_width,
+				' ' => width += 1,
+				_ => break,
+			}
+		}
+
+		return width / self.indent_size;
+
+	}
+
+}
+
+// properties that apply to a file from all .editorconfig files, nearer ones come later
+fn editorconfig(path: &Path) -> Vec<(String, String)> {
+
+	let mut files = vec![];
+	let mut dir = path.parent();
+
+	while let Some(d) = dir {
+
+		if let Ok(content) = std::fs::read_to_string(d.join(EDITORCONFIG)) {
+			let (root, sections) = parse_editorconfig(&content);
+			files.push((d.to_path_buf(), sections));
+			if root {
+				break;
+			}
+		}
+
+		dir = d.parent();
+
+	}
+
+	let mut props = vec![];
+
+	for (dir, sections) in files.into_iter().rev() {
+
+		let rel = match path.strip_prefix(&dir) {
+			Ok(rel) => rel.to_string_lossy().replace('\\', "/"),
+			Err(_) => continue,
+		};
+
+		for (glob, section) in sections {
+			if glob_matches(&glob, &rel) {
+				props.extend(section);
+			}
+		}
+
+	}
+
+	return props;
+
+}
+
+type Section = (String, Vec<(String, String)>);
+
+fn parse_editorconfig(content: &str) -> (bool, Vec<Section>) {
+
+	let mut root = false;
+	let mut sections: Vec<Section> = vec![];
+
+	for line in content.lines() {
+
+		let line = line.trim();
+
+		if line.is_empty() || line.starts_with('#') || line.starts_with(';') {
+			continue;
+		}
+
+		if line.starts_with('[') && line.ends_with(']') {
+			sections.push((line[1..line.len() - 1].to_string(), vec![]));
+			continue;
+		}
+
+		let mut kv = line.splitn(2, '=');
+
+		if let (Some(k), Some(v)) = (kv.next(), kv.next()) {
+
+			let k = k.trim().to_lowercase();
+			let v = v.trim().to_string();
+
+			match sections.last_mut() {
+				Some((_, props)) => props.push((k, v)),
+				None => {
+					if k == "root" {
+						root = v.to_lowercase() == "true";
+					}
+				},
+			}
+
+		}
+
+	}
+
+	return (root, sections);
+
+}
+
+// editorconfig globs, relative to the directory the file is in
+fn glob_matches(glob: &str, path: &str) -> bool {
+
+	// globs without a '/' match a file name in any directory
+	let glob = if glob.contains('/') {
+		glob.trim_start_matches('/').to_string()
+	} else {
+		format!("**/{}", glob)
+	};
+
+	let mut re = String::from("^");
+	let mut chars = glob.chars().peekable();
+	let mut braces = 0;
+
+	while let Some(ch) = chars.next() {
+		match ch {
+			'*' => {
+				if chars.peek() == Some(&'*') {
+					chars.next();
+					if chars.peek() == Some(&'/') {
+						// "**/" can also match nothing
+						chars.next();
+						re.push_str("(?:.*/)?");
+					} else {
+						re.push_str(".*");
+					}
+				} else {
+					re.push_str("[^/]*");
+				}
+			},
+			'?' => re.push_str("[^/]"),
+			'[' => {
+				re.push('[');
+				if chars.peek() == Some(&'!') {
+					chars.next();
+					re.push('^');
+				}
+				while let Some(c) = chars.next() {
+					if c == ']' {
+						break;
+					}
+					if c == '\\' || c == '[' || c == '^' {
+						re.push('\\');
+					}
+					re.push(c);
+				}
+				re.push(']');
+			},
+			'{' => {
+				braces += 1;
+				re.push_str("(?:");
+			},
+			'}' if braces > 0 => {
+				braces -= 1;
+				re.push(')');
+			},
+			',' if braces > 0 => re.push('|'),
+			'\\' => {
+				if let Some(c) = chars.next() {
+					re.push_str(&regex::escape(&c.to_string()));
+				}
+			},
+			_ => re.push_str(&regex::escape(&ch.to_string())),
+		}
+	}
+
+	re.push('$');
+
+	return Regex::new(&re)
+		.map(|re| re.is_match(path))
+		.unwrap_or(false);
+
+}
+
+// lines ending with "\r\n" and with only '\n', a lone '\r' isn't a line ending
+fn count_line_endings(text: &str) -> (usize, usize) {
+	let crlf = text.matches("\r\n").count();
+	return (crlf, text.matches('\n').count() - crlf);
+}
+
+/// Line ending a text mostly uses.
+pub fn detect_line_ending(text: &str) -> Option<LineEnding> {
+
+	let (crlf, lf) = count_line_endings(text);
+
+	if crlf + lf == 0 {
+		return None;
+	}
+
+	return Some(if crlf >= lf {
+		LineEnding::Crlf
+	} else {
+		LineEnding::Lf
+	});
+
+}
+
+/// If some lines end with "\r\n" and others with '\n'.
+pub fn mixed_line_endings(text: &str) -> bool {
+	let (crlf, lf) = count_line_endings(text);
+	return crlf > 0 && lf > 0;
+}
+
+/// Turn "\r\n" into '\n', a lone '\r' is left as it is.
+pub fn normalize_line_endings(text: &str) -> String {
+	return text.replace("\r\n", "\n");
+}
@@ -0,0 +1,166 @@
+// wengwengweng
+
+use regex::Regex;
+
+use super::*;
+
+impl TextEditor {
+
+	/// Add a cursor on the line above or below, the new one becomes the main cursor.
+	pub(super) fn add_cursor_vertical(&mut self, down: bool) {
+
+		let prev = self.cursor;
+
+		if down {
+			self.move_down();
+		} else {
+			self.move_up();
+		}
+
+		if self.cursor != prev && !self.cursors.contains(&prev) {
+			self.cursors.push(prev);
+		}
+
+	}
+
+	/// Add a cursor at the next match of the search pattern, or of the word under the cursor if there's none.
+	pub(super) fn add_cursor_at_match(&mut self, search: bool) {
+
+		let pat = if search {
+			self.search_pattern.clone()
+		} else {
+			self.word_at(self.cursor)
+				.and_then(|w| Regex::new(&format!(r"\b{}\b", regex::escape(&w))).ok())
+		};
+
+		let pat = match pat {
+			Some(pat) => pat,
+			None => return,
+		};
+
+		let mut from = self.cursor;
+
+		// skip matches already taken by a cursor
+		for _ in 0..=self.cursors.len() {
+
+			let pos = match self.find_next(&pat, from) {
+				Some(pos) => pos,
+				None => return,
+			};
+
+			if pos == self.cursor {
+				return;
+			}
+
+			if !self.cursors.contains(&pos) {
+				self.cursors.push(self.cursor);
+				self.move_to(pos);
+				return;
+			}
+
+			from = pos;
+
+		}
+
+	}
+
+	pub(super) fn clear_cursors(&mut self) {
+		self.cursors.clear();
+	}
+
+	/// Run a command at every cursor.
+	///
+	/// Cursors are handled from the bottom up as char indices, so an edit only moves the cursors after it, which are shifted by how much the text grew or shrank.
+	pub(super) fn exec_multi(&mut self, cmd: Command) {
+
+		let mut all = self.cursors
+			.iter()
+			.map(|c| (self.pos_to_idx(*c), false))
+			.collect::<Vec<(usize, bool)>>();
+
+		all.push((self.pos_to_idx(self.cursor), true));
+		all.sort_by(|a, b| b.0.cmp(&a.0));
+
+		let mut done: Vec<(usize, bool)> = vec![];
+
+		for (idx, main) in all {
+
+			let len = self.text.len_chars() as isize;
+
+			self.cursor = self.idx_to_pos(idx);
+			self.exec_one(cmd);
+
+			let delta = self.text.len_chars() as isize - len;
+
+			for d in &mut done {
+				d.0 = (d.0 as isize + delta).max(0) as usize;
+			}
+
+			done.push((self.pos_to_idx(self.cursor), main));
+
+		}
+
+		self.cursors.clear();
+
+		for (idx, main) in done {
+			let pos = self.idx_to_pos(idx);
+			if main {
+				self.cursor = pos;
+			} else if !self.cursors.contains(&pos) {
+				self.cursors.push(pos);
+			}
+		}
+
+		let cursor = self.cursor;
+
+		self.cursors.retain(|c| *c != cursor);
+
+	}
+
+	// next match after a position, wrapping around the end
+	fn find_next(&self, pat: &Regex, from: Cursor) -> Option<Cursor> {
+
+		let lines = self.text.len_lines();
+		let start = (from.line - 1).max(0) as usize;
+
+		for n in 0..=lines {
+
+			let i = (start + n) % lines;
+
+			let l = match self.text.line(i) {
+				Some(l) => l,
+				None => continue,
+			};
+
+			for f in pat.find_iter(&l) {
+				let col = byte_to_col(&l, f.start()) as Col + 1;
+				let pos = Cursor::new(i as Line + 1, col);
+				if n > 0 || pos > from {
+					return Some(pos);
+				}
+			}
+
+		}
+
+		return None;
+
+	}
+
+	fn word_at(&self, pos: Cursor) -> Option<String> {
+
+		let line = self.get_line_at(pos.line)?;
+		let gs = graphemes(&line).collect::<Vec<&str>>();
+		let i = (pos.col - 1).max(0) as usize;
+
+		if i >= gs.len() || is_break(gs[i]) {
+			return None;
+		}
+
+		let start = (0..i).rev().take_while(|j| !is_break(gs[*j])).last().unwrap_or(i);
+		let end = (i..gs.len()).take_while(|j| !is_break(gs[*j])).last().unwrap_or(i);
+
+		return Some(gs[start..=end].concat());
+
+	}
+
+}
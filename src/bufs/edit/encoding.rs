@@ -0,0 +1,211 @@
+// wengwengweng
+
+use super::*;
+
+const BOM_UTF8: [u8; 3] = [0xef, 0xbb, 0xbf];
+const BOM_UTF16LE: [u8; 2] = [0xff, 0xfe];
+const BOM_UTF16BE: [u8; 2] = [0xfe, 0xff];
+// how much of a file to look at when guessing utf-16
+const SNIFF_LEN: usize = 4096;
+// more control bytes than this and it's not text
+const BINARY_CONTROL_PERCENT: usize = 10;
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum Charset {
+	Utf8,
+	Utf8Bom,
+	Latin1,
+	Utf16Le,
+	Utf16LeBom,
+	Utf16Be,
+	Utf16BeBom,
+}
+
+impl Charset {
+
+	pub fn from_name(name: &str) -> Option<Self> {
+		return match name {
+			"utf-8" => Some(Charset::Utf8),
+			"utf-8-bom" => Some(Charset::Utf8Bom),
+			"latin1" => Some(Charset::Latin1),
+			"utf-16le" => Some(Charset::Utf16Le),
+			"utf-16le-bom" => Some(Charset::Utf16LeBom),
+			"utf-16be" => Some(Charset::Utf16Be),
+			"utf-16be-bom" => Some(Charset::Utf16BeBom),
+			_ => None,
+		};
+	}
+
+	pub fn name(&self) -> &'static str {
+		return match self {
+			Charset::Utf8 => "utf-8",
+			Charset::Utf8Bom => "utf-8-bom",
+			Charset::Latin1 => "latin1",
+			Charset::Utf16Le => "utf-16le",
+			Charset::Utf16LeBom => "utf-16le-bom",
+			Charset::Utf16Be => "utf-16be",
+			Charset::Utf16BeBom => "utf-16be-bom",
+		};
+	}
+
+}
+
+/// Guess the charset of some file content, from the BOM, then what utf-16 text looks like, then utf-8, and latin1 if nothing else fits.
+pub fn detect(bytes: &[u8]) -> Charset {
+
+	let bom = if bytes.starts_with(&BOM_UTF8) {
+		Some(Charset::Utf8Bom)
+	} else if bytes.starts_with(&BOM_UTF16LE) {
+		Some(Charset::Utf16LeBom)
+	} else if bytes.starts_with(&BOM_UTF16BE) {
+		Some(Charset::Utf16BeBom)
+	} else {
+		None
+	};
+
+	return bom
+		.into_iter()
+		.chain(sniff_utf16(bytes))
+		.chain(Some(Charset::Utf8))
+		.find(|c| decode(bytes, *c).is_some())
+		.unwrap_or(Charset::Latin1);
+
+}
+
+// mostly ascii utf-16 text has a 0 in every other byte
+fn sniff_utf16(bytes: &[u8]) -> Option<Charset> {
+
+	let bytes = &bytes[..bytes.len().min(SNIFF_LEN)];
+
+	if bytes.len() < 2 || bytes.len() % 2 != 0 {
+		return None;
+	}
+
+	let pairs = bytes.len() / 2;
+	let even = bytes.iter().step_by(2).filter(|b| **b == 0).count();
+	let odd = bytes.iter().skip(1).step_by(2).filter(|b| **b == 0).count();
+
+	if odd * 10 > pairs * 4 && even * 10 < pairs {
+		return Some(Charset::Utf16Le);
+	} else if even * 10 > pairs * 4 && odd * 10 < pairs {
+		return Some(Charset::Utf16Be);
+	}
+
+	return None;
+
+}
+
+/// If file content looks binary, a NUL byte anywhere or a lot of control bytes, that aren't part of utf-16 text.
+pub fn is_binary(bytes: &[u8]) -> bool {
+
+	// what shows up in text, tabs, line endings, form feeds and escapes for colors
+	let control = bytes
+		.iter()
+		.filter(|b| (**b < 0x20 && !matches!(**b, b'\t' | b'\n' | b'\r' | 0x0c | 0x1b)) || **b == 0x7f)
+		.count();
+
+	if !bytes.contains(&0) && control * 100 <= bytes.len() * BINARY_CONTROL_PERCENT {
+		return false;
+	}
+
+	return match detect(bytes) {
+		Charset::Utf16Le | Charset::Utf16LeBom | Charset::Utf16Be | Charset::Utf16BeBom => false,
+		_ => true,
+	};
+
+}
+
+/// Decode file content, `None` if it's not valid in that charset.
+pub fn decode(bytes: &[u8], charset: Charset) -> Option<String> {
+
+	let utf16 = |bytes: &[u8], le: bool| {
+
+		let bom = if le { BOM_UTF16LE } else { BOM_UTF16BE };
+		let bytes = bytes.strip_prefix(&bom).unwrap_or(bytes);
+
+		if bytes.len() % 2 != 0 {
+			return None;
+		}
+
+		let units = bytes
+			.chunks(2)
+			.map(|c| {
+				return if le { u16::from_le_bytes([c[0], c[1]]) } else { u16::from_be_bytes([c[0], c[1]]) };
+			})
+			.collect::<Vec<u16>>();
+
+		return String::from_utf16(&units).ok();
+
+	};
+
+	return match charset {
+		// a stray BOM is dropped for plain utf-8 too
+		Charset::Utf8 | Charset::Utf8Bom => {
+			let bytes = bytes.strip_prefix(&BOM_UTF8).unwrap_or(bytes);
+			String::from_utf8(bytes.to_vec()).ok()
+		},
+		Charset::Latin1 => Some(bytes.iter().map(|b| *b as char).collect()),
+		// and for utf-16 without one
+		Charset::Utf16Le | Charset::Utf16LeBom => utf16(bytes, true),
+		Charset::Utf16Be | Charset::Utf16BeBom => utf16(bytes, false),
+	};
+
+}
+
+/// Encode text for writing to a file, with a BOM only for the charsets that have one, an error if some of it doesn't fit in the charset.
+pub fn encode(text: &str, charset: Charset) -> Result<Vec<u8>> {
+
+	let utf16 = |bom: bool, le: bool| {
+		return bom
+			.then(|| 0xfeffu16)
+			.into_iter()
+			.chain(text.encode_utf16())
+			.flat_map(|u| if le { u.to_le_bytes() } else { u.to_be_bytes() }.to_vec())
+			.collect::<Vec<u8>>();
+	};
+
+	return Ok(match charset {
+		Charset::Utf8 => text.as_bytes().to_vec(),
+		Charset::Utf8Bom => [&BOM_UTF8, text.as_bytes()].concat(),
+		Charset::Latin1 => {
+			if let Some(c) = text.chars().find(|c| (*c as u32) >= 256) {
+				return Err(format!("'{}' can't be saved as {}, :set charset=utf-8 to keep it", c, charset.name()));
+			}
+			text.chars().map(|c| c as u8).collect()
+		},
+		Charset::Utf16Le => utf16(false, true),
+		Charset::Utf16LeBom => utf16(true, true),
+		Charset::Utf16Be => utf16(false, false),
+		Charset::Utf16BeBom => utf16(true, false),
+	});
+
+}
+
+impl TextEditor {
+
+	/// Read the file again as a charset, or a detected one.
+	pub(super) fn reload(&mut self, charset: Option<Charset>) -> Result<()> {
+
+		if self.scratch {
+			return Err(format!("not a file"));
+		}
+
+		if self.is_modified() {
+			return Err(format!("unsaved changes"));
+		}
+
+		if let Some(c) = charset {
+			let bytes = std::fs::read(&self.path)
+				.map_err(|_| format!("failed to read {}", self.path.display()))?;
+			if decode(&bytes, c).is_none() {
+				return Err(format!("not valid {}", c.name()));
+			}
+		}
+
+		self.conf.set_charset(charset);
+
+		return self.read_disk();
+
+	}
+
+}
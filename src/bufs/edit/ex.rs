@@ -0,0 +1,465 @@
+// wengwengweng
+
+use std::io::Write;
+use std::process::Stdio;
+
+use super::*;
+
+/// A command typed in the command bar after ':'.
+#[derive(Clone, Debug, PartialEq)]
+enum Ex {
+	Goto,
+	Write(Option<String>),
+	Edit(String),
+	Reload(Option<String>),
+	Quit,
+	Substitute(String),
+	Sort,
+	Set(String, String),
+	Filter(String),
+	Fold,
+	Unfold,
+	FoldLevel(usize),
+	Definition,
+	References,
+	Hover,
+	Rename(String),
+	Revert,
+}
+
+/// A parsed command, with the line range it was given if any.
+#[derive(Clone, Debug, PartialEq)]
+struct ExCmd {
+	range: Option<(Line, Line)>,
+	cmd: Ex,
+}
+
+impl ExCmd {
+
+	// ranges are "%", or one or two addresses separated by ',', an address is a line number, "." (current line) or "$" (last line), followed by any number of "+n" / "-n"
+	fn parse(s: &str, cur: Line, last: Line) -> Result<Self> {
+
+		let s = s.trim_start();
+
+		let (range, rest) = if let Some(rest) = s.strip_prefix('%') {
+			(Some((1, last)), rest)
+		} else {
+			match parse_addr(s, cur, last)? {
+				Some((a, rest)) => {
+					if let Some(rest) = rest.strip_prefix(',') {
+						match parse_addr(rest, cur, last)? {
+							Some((b, rest)) => (Some((a, b)), rest),
+							None => return Err(format!("expected address after ','")),
+						}
+					} else {
+						(Some((a, a)), rest)
+					}
+				},
+				None => (None, s),
+			}
+		};
+
+		if let Some((a, b)) = range {
+			for n in [a, b].iter() {
+				if *n < 1 || *n > last {
+					return Err(format!("line {} is out of range", n));
+				}
+			}
+		}
+
+		let range = range.map(|(a, b)| (a.min(b), a.max(b)));
+		let rest = rest.trim();
+
+		let cmd = if let Some(cmd) = rest.strip_prefix('!') {
+			Ex::Filter(cmd.trim().to_string())
+		} else if rest.starts_with("s/") {
+			Ex::Substitute(rest.to_string())
+		} else {
+
+			let (name, arg) = match rest.find(char::is_whitespace) {
+				Some(i) => (&rest[..i], rest[i..].trim()),
+				None => (rest, ""),
+			};
+
+			match name {
+				"" => Ex::Goto,
+				"w" | "write" => {
+					if arg.is_empty() {
+						Ex::Write(None)
+					} else {
+						Ex::Write(Some(arg.to_string()))
+					}
+				},
+				"e" | "edit" => {
+					if arg.is_empty() {
+						return Err(format!("expected a path"));
+					}
+					Ex::Edit(arg.to_string())
+				},
+				"reload" => {
+					if arg.is_empty() {
+						Ex::Reload(None)
+					} else {
+						Ex::Reload(Some(arg.to_string()))
+					}
+				},
+				"q" | "quit" => Ex::Quit,
+				"sort" => Ex::Sort,
+				"fold" => Ex::Fold,
+				"unfold" => Ex::Unfold,
+				"def" => Ex::Definition,
+				"refs" => Ex::References,
+				"hover" => Ex::Hover,
+				"revert" => Ex::Revert,
+				"rename" => {
+					if arg.is_empty() {
+						return Err(format!("expected a name"));
+					}
+					Ex::Rename(arg.to_string())
+				},
+				"foldlevel" => {
+					let level = arg
+						.parse::<usize>()
+						.map_err(|_| format!("expected a level"))?;
+					Ex::FoldLevel(level)
+				},
+				"set" => {
+					let mut kv = arg.splitn(2, '=');
+					match (kv.next(), kv.next()) {
+						(Some(k), Some(v)) if !k.is_empty() => Ex::Set(k.trim().to_string(), v.trim().to_string()),
+						_ => return Err(format!("expected option=value")),
+					}
+				},
+				_ => return Err(format!("unknown command '{}'", name)),
+			}
+
+		};
+
+		return Ok(Self {
+			range: range,
+			cmd: cmd,
+		});
+
+	}
+
+}
+
+fn parse_addr(s: &str, cur: Line, last: Line) -> Result<Option<(Line, &str)>> {
+
+	let digits = s.chars().take_while(|c| c.is_ascii_digit()).count();
+
+	let (mut line, mut rest) = if let Some(rest) = s.strip_prefix('.') {
+		(cur, rest)
+	} else if let Some(rest) = s.strip_prefix('$') {
+		(last, rest)
+	} else if digits > 0 {
+		let n = s[..digits]
+			.parse::<Line>()
+			.map_err(|_| format!("invalid line number"))?;
+		(n, &s[digits..])
+	} else if s.starts_with('+') || s.starts_with('-') {
+		(cur, s)
+	} else {
+		return Ok(None);
+	};
+
+	while let Some(sign) = rest.chars().next().filter(|c| *c == '+' || *c == '-') {
+
+		let num = &rest[1..];
+		let digits = num.chars().take_while(|c| c.is_ascii_digit()).count();
+
+		let n = if digits > 0 {
+			num[..digits]
+				.parse::<Line>()
+				.map_err(|_| format!("invalid offset"))?
+		} else {
+			1
+		};
+
+		line = if sign == '+' { line + n } else { line - n };
+		rest = &num[digits..];
+
+	}
+
+	return Ok(Some((line, rest)));
+
+}
+
+impl TextEditor {
+
+	pub(super) fn open_cmd_bar(&mut self, prefix: &str) {
+
+		if self.mode == Mode::Select {
+			let (start, end) = self.sel_bounds();
+			self.stop_select();
+			self.cmd_range = Some((start.line, end.line));
+		}
+
+		self.mode = Mode::Command;
+		self.cmd_bar = Input::new();
+
+		for ch in prefix.chars() {
+			self.cmd_bar.insert(ch);
+		}
+
+	}
+
+	/// Run what's in the command bar, a command when it starts with ':', a search otherwise.
+	pub(super) fn run_cmd(&mut self) {
+
+		let content = self.cmd_bar.content().to_string();
+		let sel = self.cmd_range.take();
+
+		let cmd = match content.strip_prefix(':') {
+			Some(cmd) => cmd,
+			None => {
+				match regex::Regex::new(&content) {
+					Ok(re) => self.search_pattern = Some(re),
+					Err(_) => self.log.push(Msg::error(&format!("invalid pattern '{}'", content))),
+				}
+				return;
+			},
+		};
+
+		if let Err(e) = self.exec_ex(cmd, sel) {
+			self.log.push(Msg::error(&e));
+		}
+
+	}
+
+	fn exec_ex(&mut self, cmd: &str, sel: Option<(Line, Line)>) -> Result<()> {
+
+		let last = self.text.len_lines() as Line;
+		let cmd = ExCmd::parse(cmd, self.cursor.line, last)?;
+		let range = cmd.range.or(sel);
+		let cur = (self.cursor.line, self.cursor.line);
+
+		match cmd.cmd {
+
+			Ex::Goto => {
+				if let Some((_, l)) = range {
+					self.move_to(self.line_start_at(Cursor::new(l, 1)));
+				}
+			},
+
+			Ex::Write(path) => {
+				match path {
+					Some(path) => self.save_as(self.resolve_path(&path))?,
+					None => self.save()?,
+				}
+				self.log.push(Msg::success(&format!("written to {}", self.path.display())));
+			},
+
+			Ex::Edit(path) => {
+				self.actions.push(Action::Open(self.resolve_path(&path)));
+			},
+
+			Ex::Reload(charset) => {
+				let charset = match charset {
+					Some(c) => Some(Charset::from_name(&c).ok_or_else(|| format!("invalid charset '{}'", c))?),
+					None => None,
+				};
+				self.reload(charset)?;
+			},
+
+			Ex::Quit => {
+				if self.is_modified() {
+					return Err(format!("unsaved changes"));
+				}
+				self.actions.push(Action::Close);
+			},
+
+			Ex::Substitute(s) => {
+				let (l1, l2) = range.unwrap_or(cur);
+				self.substitute(Substitute::parse(&s)?, l1, l2);
+			},
+
+			Ex::Sort => {
+
+				let (l1, l2) = range.unwrap_or((1, last));
+
+				let mut lines = (l1..=l2)
+					.filter_map(|ln| self.get_line_at(ln))
+					.collect::<Vec<String>>();
+
+				lines.sort();
+				self.replace_lines(l1, l2, &lines.join("\n"));
+
+			},
+
+			Ex::Set(k, v) => {
+				match k.as_str() {
+					"tabwidth" | "tw" => {
+						self.conf.tab_width = v
+							.parse::<usize>()
+							.ok()
+							.filter(|n| *n > 0)
+							.ok_or_else(|| format!("invalid tab width '{}'", v))?;
+					},
+					"indentsize" | "is" => {
+						self.conf.indent_size = v
+							.parse::<usize>()
+							.ok()
+							.filter(|n| *n > 0)
+							.ok_or_else(|| format!("invalid indent size '{}'", v))?;
+					},
+					"indentstyle" => {
+						self.conf.indent_style = match v.as_str() {
+							"tab" => IndentStyle::Tab,
+							"space" => IndentStyle::Space,
+							_ => return Err(format!("invalid indent style '{}'", v)),
+						};
+					},
+					"eol" => {
+						self.conf.set_end_of_line(LineEnding::from_name(&v)
+							.ok_or_else(|| format!("invalid line ending '{}'", v))?);
+					},
+					"charset" => {
+						self.conf.set_charset(Some(Charset::from_name(&v)
+							.ok_or_else(|| format!("invalid charset '{}'", v))?));
+					},
+					"wrap" => {
+						self.conf.soft_wrap = v
+							.parse::<bool>()
+							.map_err(|_| format!("expected true or false"))?;
+					},
+					// 0 wraps at the view width
+					"wrapcol" => {
+						let n = v
+							.parse::<usize>()
+							.map_err(|_| format!("invalid column '{}'", v))?;
+						self.conf.wrap_column = if n == 0 { None } else { Some(n) };
+					},
+					"wrapindent" => {
+						self.conf.wrap_indent = v
+							.parse::<bool>()
+							.map_err(|_| format!("expected true or false"))?;
+					},
+					// 0 only completes when asked
+					"completedelay" => {
+						let n = v
+							.parse::<u64>()
+							.map_err(|_| format!("invalid delay '{}'", v))?;
+						self.complete_delay = if n == 0 { None } else { Some(Duration::from_millis(n)) };
+					},
+					"numbers" => {
+						self.conf.line_numbers = LineNumbers::from_name(&v)
+							.ok_or_else(|| format!("invalid line numbers '{}'", v))?;
+					},
+					"finalnewline" => {
+						self.conf.set_final_newline(v
+							.parse::<bool>()
+							.map_err(|_| format!("expected true or false"))?);
+					},
+					_ => return Err(format!("unknown option '{}'", k)),
+				}
+			},
+
+			Ex::Filter(cmd) => {
+
+				match range {
+					// pipe the lines through the command
+					Some((l1, l2)) => {
+						let input = (l1..=l2)
+							.filter_map(|ln| self.get_line_at(ln))
+							.collect::<Vec<String>>()
+							.join("\n");
+						let output = shell(&cmd, Some(&format!("{}\n", input)))?;
+						self.replace_lines(l1, l2, output.strip_suffix('\n').unwrap_or(&output));
+					},
+					// just run it
+					None => {
+						let output = shell(&cmd, None)?;
+						for line in output.lines() {
+							self.log.push(Msg::info(line));
+						}
+					},
+				}
+
+			},
+
+			Ex::Fold => {
+				match range {
+					Some((l1, l2)) => self.fold(l1 as usize - 1, l2 as usize - 1),
+					None => self.toggle_fold(),
+				}
+			},
+
+			Ex::Unfold => self.unfold_all(),
+			Ex::FoldLevel(level) => self.fold_level(level),
+			Ex::Definition => self.goto_definition(),
+			Ex::References => self.find_references(),
+			Ex::Hover => self.request_hover(),
+			Ex::Rename(name) => self.rename(&name),
+			Ex::Revert => self.revert_hunk(range),
+
+		}
+
+		return Ok(());
+
+	}
+
+	fn resolve_path(&self, path: &str) -> PathBuf {
+
+		let path = expand_path(path);
+
+		if path.is_absolute() {
+			return path;
+		}
+
+		return self.path
+			.parent()
+			.map(|dir| dir.join(&path))
+			.unwrap_or(path);
+
+	}
+
+	// replace lines l1 to l2 with some text
+	fn replace_lines(&mut self, l1: Line, l2: Line, text: &str) {
+
+		let start = self.pos_to_idx(Cursor::new(l1, 1));
+		let end = self.pos_to_idx(Cursor::new(l2, self.line_len_at(l2).unwrap_or(0) + 1));
+
+		if self.text.slice(start, end) != text {
+			self.text.remove(start, end);
+			self.text.insert(start, text);
+			self.move_to(self.line_start_at(Cursor::new(l1, 1)));
+			self.highlight();
+		}
+
+	}
+
+}
+
+fn shell(cmd: &str, input: Option<&str>) -> Result<String> {
+
+	let mut child = std::process::Command::new("sh")
+		.arg("-c")
+		.arg(cmd)
+		.stdin(if input.is_some() { Stdio::piped() } else { Stdio::null() })
+		.stdout(Stdio::piped())
+		.stderr(Stdio::piped())
+		.spawn()
+		.map_err(|_| format!("failed to run '{}'", cmd))?;
+
+	if let (Some(input), Some(mut stdin)) = (input, child.stdin.take()) {
+		// write from another thread so a full stdout pipe can't block us
+		let input = input.to_string();
+		std::thread::spawn(move || {
+			stdin.write_all(input.as_bytes()).ok();
+		});
+	}
+
+	let out = child
+		.wait_with_output()
+		.map_err(|_| format!("failed to run '{}'", cmd))?;
+
+	if !out.status.success() {
+		let err = String::from_utf8_lossy(&out.stderr);
+		return Err(format!("'{}' failed: {}", cmd, err.trim()));
+	}
+
+	return String::from_utf8(out.stdout)
+		.map_err(|_| format!("'{}' gave invalid utf-8", cmd));
+
+}
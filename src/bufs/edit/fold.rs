@@ -0,0 +1,267 @@
+// wengwengweng
+
+use super::*;
+use super::brackets::opener_of;
+
+fn indent_width(line: &str, tab_width: usize) -> Option<usize> {
+
+	if line.trim().is_empty() {
+		return None;
+	}
+
+	let indent = graphemes(line)
+		.take_while(|g| *g == " " || *g == "\t")
+		.count();
+
+	return Some(display_col(line, indent, tab_width));
+
+}
+
+// the bracket a line opens a scope with, if it ends with one
+fn scope_opener(line: &str) -> Option<(char, char)> {
+	let ch = line.trim_end().chars().last()?;
+	return SCOPE_CHARS.get(&ch).map(|close| (ch, *close));
+}
+
+impl TextEditor {
+
+	// the closed fold a line is hidden in, folds are sorted and don't overlap so the one before it is the only one that can be
+	pub(super) fn fold_around(&self, i: usize) -> Option<(usize, usize)> {
+		let n = self.folds.partition_point(|(s, _)| *s < i);
+		return n
+			.checked_sub(1)
+			.map(|n| self.folds[n])
+			.filter(|(_, e)| i <= *e);
+	}
+
+	/// If a line is inside a closed fold.
+	pub(super) fn is_hidden(&self, i: usize) -> bool {
+		return self.fold_around(i).is_some();
+	}
+
+	/// Closed fold starting at a line.
+	pub(super) fn fold_at(&self, i: usize) -> Option<(usize, usize)> {
+		return self.folds
+			.binary_search_by_key(&i, |(s, _)| *s)
+			.ok()
+			.map(|n| self.folds[n]);
+	}
+
+	/// The closest line that's not folded away, below or above.
+	pub(super) fn next_visible(&self, i: usize, down: bool) -> Option<usize> {
+
+		if down {
+
+			let mut j = i + 1;
+
+			while j < self.text.len_lines() {
+				match self.fold_around(j) {
+					Some((_, e)) => j = e + 1,
+					None => return Some(j),
+				}
+			}
+
+			return None;
+
+		} else {
+			let j = i.checked_sub(1)?;
+			return Some(self.fold_around(j).map(|(s, _)| s).unwrap_or(j));
+		}
+
+	}
+
+	// every scope, sorted by the line it starts at, a line opens one with a bracket at its end or by the lines after it being indented more
+	fn scopes(&self) -> Vec<(usize, usize)> {
+
+		let tab_width = self.conf.tab_width;
+		let mut scopes = vec![];
+		// line of each bracket still open, by bracket, and if it ends its line and so opens a scope
+		let mut open: HashMap<char, Vec<(usize, bool)>> = hmap![];
+		// lines that might open an indent scope and how indented they are, more indented last
+		let mut indents: Vec<(usize, usize)> = vec![];
+		let mut last_text = 0;
+
+		for (i, line) in self.text.lines().enumerate() {
+
+			// char the opener is at, one in a comment doesn't count
+			let opener_ci = line.trim_end().chars().count().saturating_sub(1);
+			let opener = scope_opener(&line).filter(|_| !self.in_literal(i, opener_ci));
+
+			for (ci, ch) in line.chars().enumerate() {
+
+				let is_open = SCOPE_CHARS.contains_key(&ch);
+				let close_of = opener_of(ch);
+
+				if (!is_open && close_of.is_none()) || self.in_literal(i, ci) {
+					continue;
+				}
+
+				if is_open {
+					open
+						.entry(ch)
+						.or_insert(vec![])
+						.push((i, opener.is_some() && ci == opener_ci));
+				} else if let Some((s, true)) = close_of.and_then(|o| open.get_mut(&o)?.pop()) {
+					if i > s {
+						scopes.push((s, i));
+					}
+				}
+
+			}
+
+			let w = match indent_width(&line, tab_width) {
+				Some(w) => w,
+				// blank lines belong to the scope only if it continues after them
+				None => continue,
+			};
+
+			while let Some((s, _)) = indents.last().filter(|(_, iw)| *iw >= w).cloned() {
+				indents.pop();
+				if last_text > s {
+					scopes.push((s, last_text));
+				}
+			}
+
+			if opener.is_none() {
+				indents.push((i, w));
+			}
+
+			last_text = i;
+
+		}
+
+		for (s, _) in indents {
+			if last_text > s {
+				scopes.push((s, last_text));
+			}
+		}
+
+		scopes.sort_unstable();
+
+		return scopes;
+
+	}
+
+	// the innermost scope around a line, starting at it or above
+	fn scope_around(&self, i: usize) -> Option<(usize, usize)> {
+		return self.scopes()
+			.into_iter()
+			.filter(|(s, e)| *s <= i && i <= *e)
+			.last();
+	}
+
+	/// Close the scope around the cursor, or open it if it's closed.
+	pub(super) fn toggle_fold(&mut self) {
+
+		let i = (self.cursor.line - 1).max(0) as usize;
+
+		if let Some(f) = self.fold_at(i) {
+			self.folds.retain(|r| *r != f);
+			self.recount_rows(f.0);
+			return;
+		}
+
+		match self.scope_around(i) {
+			Some(r) => self.fold(r.0, r.1),
+			None => self.log.push(Msg::error("nothing to fold")),
+		}
+
+	}
+
+	/// Fold lines `s` to `e`, `s` stays visible as the placeholder.
+	///
+	/// Folds inside it are taken in, so they're opened with it.
+	pub(super) fn fold(&mut self, s: usize, e: usize) {
+
+		if e <= s || self.is_hidden(s) || self.fold_at(s).map(|(_, fe)| fe >= e).unwrap_or(false) {
+			return;
+		}
+
+		let inside = |fs: usize| s <= fs && fs <= e;
+		let e = self.folds
+			.iter()
+			.filter(|(fs, _)| inside(*fs))
+			.map(|(_, fe)| *fe)
+			.fold(e, usize::max);
+
+		self.folds.retain(|(fs, _)| !inside(*fs));
+		self.folds.insert(self.folds.partition_point(|(fs, _)| *fs < s), (s, e));
+		self.recount_rows(s);
+		self.move_to(Cursor::new(s as Line + 1, self.cursor.col));
+
+	}
+
+	pub(super) fn unfold_all(&mut self) {
+		self.folds.clear();
+		self.recount_rows(0);
+	}
+
+	/// Close every scope nested `level` deep or deeper, 0 folds everything, the ones in others are taken in by them.
+	pub(super) fn fold_level(&mut self, level: usize) {
+
+		let mut ends: Vec<usize> = vec![];
+
+		self.folds.clear();
+
+		for r in self.scopes() {
+
+			let i = r.0;
+
+			ends.retain(|e| *e >= i);
+
+			// scopes come in order, so only the last one can have this inside
+			if ends.len() >= level && self.folds.last().map(|(_, e)| *e < i).unwrap_or(true) {
+				self.folds.push(r);
+			}
+
+			ends.push(r.1);
+
+		}
+
+		self.recount_rows(0);
+
+		let i = (self.cursor.line - 1).max(0) as usize;
+
+		if self.is_hidden(i) {
+			if let Some(v) = self.next_visible(i, false) {
+				self.move_to(Cursor::new(v as Line + 1, 1));
+			}
+		}
+
+	}
+
+	/// Keep folds in place when lines above them change, and open the ones that had lines inside changed.
+	pub(super) fn shift_folds(&mut self, first: usize, old_end: usize, new_end: usize) {
+
+		let delta = new_end as isize - old_end as isize;
+		// opened folds show lines from where they start
+		let mut opened = None;
+
+		self.folds = self.folds
+			.iter()
+			.filter_map(|(s, e)| {
+				if old_end <= *s || (first == *s && old_end == s + 1 && new_end == s + 1) {
+					let shift = if old_end <= *s { delta } else { 0 };
+					return Some(((*s as isize + shift) as usize, (*e as isize + shift) as usize));
+				} else if first > *e {
+					return Some((*s, *e));
+				}
+				opened = opened.or(Some(*s));
+				return None;
+			})
+			.collect();
+
+		self.recount_rows(opened.unwrap_or(first).min(first));
+
+	}
+
+	/// Open folds hiding the cursor, when something jumped into one.
+	pub(super) fn reveal_cursor(&mut self) {
+		let i = (self.cursor.line - 1).max(0) as usize;
+		if let Some((s, _)) = self.fold_around(i).filter(|(s, _)| *s < i) {
+			self.folds.retain(|(fs, _)| *fs != s);
+			self.recount_rows(s);
+		}
+	}
+
+}
@@ -0,0 +1,448 @@
+// wengwengweng
+
+use std::time::Instant;
+use std::time::Duration;
+use std::time::SystemTime;
+
+use super::*;
+
+// how long after edits the markers are diffed again
+const DIFF_DELAY: Duration = Duration::from_millis(200);
+// how often HEAD is checked for commits and checkouts
+const HEAD_CHECK_INTERVAL: Duration = Duration::from_secs(1);
+// line numbers are right aligned to at least this many
+const GUTTER_MIN_DIGITS: usize = 2;
+const GUTTER_MARKER_WIDTH: f32 = 3.0;
+const GUTTER_PADDING: f32 = 6.0;
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum HunkKind {
+	Added,
+	Modified,
+	Deleted,
+}
+
+/// Lines that differ from HEAD, 0-based and end exclusive, `new` in the buffer and `old` in HEAD.
+///
+/// A deletion has no lines in the buffer, `new` is where they'd go back.
+#[derive(Clone, Debug, PartialEq)]
+pub struct Hunk {
+	pub kind: HunkKind,
+	pub old: (usize, usize),
+	pub new: (usize, usize),
+}
+
+impl Hunk {
+	// the line the marker is on, a deletion goes on the line above it
+	fn line(&self) -> usize {
+		return match self.kind {
+			HunkKind::Deleted => self.new.0.saturating_sub(1),
+			_ => self.new.0,
+		};
+	}
+}
+
+/// The file in HEAD and what's changed from it.
+#[derive(Clone, Debug)]
+pub struct GitState {
+	// None if the file isn't in a repo
+	head: Option<String>,
+	pub hunks: Vec<Hunk>,
+	// edited since last diffed
+	changed: Option<Instant>,
+	// files that say what HEAD is and when they were last modified
+	head_files: Vec<(PathBuf, Option<SystemTime>)>,
+	head_checked: Instant,
+}
+
+impl GitState {
+	pub fn new() -> Self {
+		return Self {
+			head: None,
+			hunks: vec![],
+			changed: None,
+			head_files: vec![],
+			head_checked: Instant::now(),
+		};
+	}
+}
+
+pub fn hunk_color(k: HunkKind) -> Color {
+	return match k {
+		HunkKind::Added => rgba!(0.3, 0.9, 0.4, 1),
+		HunkKind::Modified => rgba!(0.4, 0.6, 1, 1),
+		HunkKind::Deleted => rgba!(1, 0.3, 0.3, 1),
+	};
+}
+
+// content of a file in HEAD, empty if it's new to the repo and None if it's not in one or ignored
+fn head_content(path: &Path) -> Option<String> {
+
+	let path = path
+		.parent()?
+		.canonicalize()
+		.ok()?
+		.join(path.file_name()?);
+
+	let repo = git2::Repository::discover(path.parent()?).ok()?;
+	let workdir = repo.workdir()?.canonicalize().ok()?;
+	let rel = path.strip_prefix(&workdir).ok()?;
+
+	if repo.status_should_ignore(rel).unwrap_or(false) {
+		return None;
+	}
+
+	// no commits yet, or not committed
+	let entry = match repo.head().and_then(|h| h.peel_to_tree()).and_then(|t| t.get_path(rel)) {
+		Ok(entry) => entry,
+		Err(_) => return Some(String::new()),
+	};
+
+	let blob = entry
+		.to_object(&repo)
+		.ok()?
+		.peel_to_blob()
+		.ok()?;
+
+	if blob.is_binary() {
+		return None;
+	}
+
+	return Some(normalize_line_endings(&String::from_utf8_lossy(blob.content())));
+
+}
+
+// HEAD, the branch it's on and packed-refs, a commit or checkout changes one of them
+fn head_files(path: &Path) -> Option<Vec<PathBuf>> {
+
+	let dir = path.parent()?.canonicalize().ok()?;
+	let repo = git2::Repository::discover(dir).ok()?;
+	let git = repo.path().to_path_buf();
+
+	// a worktree has its own HEAD but shares refs with the main repo
+	let common = std::fs::read_to_string(git.join("commondir"))
+		.map(|c| git.join(c.trim()))
+		.unwrap_or(git.clone());
+
+	let mut files = vec![git.join("HEAD"), common.join("packed-refs")];
+
+	if let Some(target) = repo.find_reference("HEAD").ok().and_then(|r| r.symbolic_target().map(String::from)) {
+		files.push(common.join(target));
+	}
+
+	return Some(files);
+
+}
+
+// every line ends with '\n' so the last one compares the same with or without it
+fn with_final_newline(mut s: String) -> String {
+	if !s.is_empty() && !s.ends_with('\n') {
+		s.push('\n');
+	}
+	return s;
+}
+
+fn diff(old: &str, new: &str) -> Vec<Hunk> {
+
+	let mut opts = git2::DiffOptions::new();
+
+	opts.context_lines(0);
+
+	let patch = match git2::Patch::from_buffers(old.as_bytes(), None, new.as_bytes(), None, Some(&mut opts)) {
+		Ok(p) => p,
+		Err(_) => return vec![],
+	};
+
+	return (0..patch.num_hunks())
+		.filter_map(|i| patch.hunk(i).ok())
+		.map(|(h, _)| {
+
+			let (os, ol) = (h.old_start() as usize, h.old_lines() as usize);
+			let (ns, nl) = (h.new_start() as usize, h.new_lines() as usize);
+
+			// starts are 1-based, but the line before when there are none
+			return match (ol, nl) {
+				(0, _) => Hunk {
+					kind: HunkKind::Added,
+					old: (os, os),
+					new: (ns - 1, ns - 1 + nl),
+				},
+				(_, 0) => Hunk {
+					kind: HunkKind::Deleted,
+					old: (os - 1, os - 1 + ol),
+					new: (ns, ns),
+				},
+				_ => Hunk {
+					kind: HunkKind::Modified,
+					old: (os - 1, os - 1 + ol),
+					new: (ns - 1, ns - 1 + nl),
+				},
+			};
+
+		})
+		.collect();
+
+}
+
+impl TextEditor {
+
+	/// Read the file from HEAD again, after it's saved or reloaded or HEAD moved.
+	pub(super) fn git_reload(&mut self) {
+
+		if self.scratch {
+			self.git.head = None;
+			self.git.head_files = vec![];
+		} else {
+			self.git.head = head_content(&self.path).map(with_final_newline);
+			self.git.head_files = head_files(&self.path)
+				.unwrap_or_default()
+				.into_iter()
+				.map(|f| {
+					let t = mtime(&f);
+					return (f, t);
+				})
+				.collect();
+		}
+
+		self.git.head_checked = Instant::now();
+		self.git.changed = Some(Instant::now() - DIFF_DELAY);
+
+	}
+
+	// commits and checkouts from anywhere, the file browser or a terminal
+	fn check_head(&mut self) {
+
+		if self.git.head_checked.elapsed() < HEAD_CHECK_INTERVAL {
+			return;
+		}
+
+		self.git.head_checked = Instant::now();
+
+		if self.git.head_files.iter().any(|(f, t)| mtime(f) != *t) {
+			self.git_reload();
+		}
+
+	}
+
+	pub(super) fn git_changed(&mut self) {
+		if self.git.changed.is_none() {
+			self.git.changed = Some(Instant::now());
+		}
+	}
+
+	/// Diff against HEAD a moment after edits.
+	pub(super) fn update_git(&mut self) {
+
+		self.check_head();
+
+		if !self.git.changed.map(|t| t.elapsed() >= DIFF_DELAY).unwrap_or(false) {
+			return;
+		}
+
+		self.git.changed = None;
+
+		self.git.hunks = match &self.git.head {
+			Some(head) => diff(head, &with_final_newline(self.content())),
+			None => vec![],
+		};
+
+	}
+
+	/// If the file's in a repo, so there's a column for markers.
+	pub(super) fn in_git(&self) -> bool {
+		return self.git.head.is_some();
+	}
+
+	/// The marker on a line, if a hunk starts, covers or was deleted right after it.
+	pub(super) fn line_hunk(&self, i: usize) -> Option<&Hunk> {
+		return self.git.hunks
+			.iter()
+			.find(|h| {
+				return match h.kind {
+					HunkKind::Deleted => h.line() == i,
+					_ => i >= h.new.0 && i < h.new.1,
+				};
+			});
+	}
+
+	/// Jump to the next or previous changed lines, wrapping around.
+	pub(super) fn next_hunk(&mut self, forward: bool) {
+
+		// the diff might be behind the edits
+		if self.git.changed.is_some() {
+			self.git.changed = Some(Instant::now() - DIFF_DELAY);
+			self.update_git();
+		}
+
+		let cur = (self.cursor.line - 1).max(0) as usize;
+		let hunks = &self.git.hunks;
+
+		let hunk = if forward {
+			hunks
+				.iter()
+				.find(|h| h.line() > cur)
+				.or(hunks.first())
+		} else {
+			hunks
+				.iter()
+				.rev()
+				.find(|h| h.line() < cur)
+				.or(hunks.last())
+		};
+
+		match hunk.map(|h| h.line()) {
+			Some(line) => self.move_to(Cursor::new(line as Line + 1, 1)),
+			None => self.log.push(Msg::info("no changes")),
+		}
+
+	}
+
+	/// Put back what HEAD has for the changed lines at the cursor, or all in a range of lines.
+	pub(super) fn revert_hunk(&mut self, range: Option<(Line, Line)>) {
+
+		if self.git.changed.is_some() {
+			self.git.changed = Some(Instant::now() - DIFF_DELAY);
+			self.update_git();
+		}
+
+		let cur = (self.cursor.line - 1).max(0) as usize;
+		let (l1, l2) = range
+			.map(|(a, b)| ((a - 1).max(0) as usize, (b - 1).max(0) as usize))
+			.unwrap_or((cur, cur));
+
+		let hunks = self.git.hunks
+			.iter()
+			.filter(|h| {
+				let (s, e) = match h.kind {
+					HunkKind::Deleted => (h.line(), h.line()),
+					_ => (h.new.0, h.new.1 - 1),
+				};
+				return s <= l2 && e >= l1;
+			})
+			.cloned()
+			.collect::<Vec<Hunk>>();
+
+		let head = match (&self.git.head, hunks.is_empty()) {
+			(Some(head), false) => head.clone(),
+			_ => {
+				self.log.push(Msg::info("no changes here"));
+				return;
+			},
+		};
+
+		let old_lines = head.lines().collect::<Vec<&str>>();
+
+		self.commit();
+
+		// last first so earlier lines stay put
+		for h in hunks.iter().rev() {
+
+			let old = old_lines[h.old.0.min(old_lines.len())..h.old.1.min(old_lines.len())]
+				.iter()
+				.map(|l| format!("{}\n", l))
+				.collect::<String>();
+
+			let len_lines = self.text.len_lines();
+			let start = self.text.pos_to_char(h.new.0, 0);
+
+			// the last line has no '\n' after it
+			if h.new.1 < len_lines {
+				let end = self.text.pos_to_char(h.new.1, 0);
+				self.text.remove(start, end);
+				self.text.insert(start, &old);
+			} else if h.new.0 < len_lines {
+				let end = self.text.len_chars();
+				if old.is_empty() && start > 0 {
+					// with the '\n' before, or there'd be an empty line left
+					self.text.remove(start - 1, end);
+				} else {
+					self.text.remove(start, end);
+					self.text.insert(start, old.strip_suffix('\n').unwrap_or(&old));
+				}
+			} else {
+				let end = self.text.len_chars();
+				self.text.insert(end, &format!("\n{}", old.strip_suffix('\n').unwrap_or(&old)));
+			}
+
+		}
+
+		self.commit();
+
+		if let Some(h) = hunks.first() {
+			self.move_to(Cursor::new(h.new.0.min(self.text.len_lines().saturating_sub(1)) as Line + 1, 1));
+		}
+
+		self.highlight();
+
+	}
+
+	/// Space the line numbers and markers take, left of the text.
+	pub(super) fn gutter_width(&self, cell: f32) -> f32 {
+
+		let digits = match self.conf.line_numbers {
+			LineNumbers::Off => 0,
+			_ => self.text.len_lines().to_string().len().max(GUTTER_MIN_DIGITS),
+		};
+
+		if digits == 0 && !self.in_git() {
+			return 0.0;
+		}
+
+		return digits as f32 * cell + GUTTER_MARKER_WIDTH + GUTTER_PADDING * 2.0;
+
+	}
+
+	/// Line number and change marker of the line at `y`.
+	pub(super) fn draw_gutter(&self, gfx: &mut Gfx, i: usize, y: f32) -> Result<()> {
+
+		let cur = (self.cursor.line - 1).max(0) as usize;
+		let cell = self.wrap.cell;
+		let padding = 2.0;
+
+		if let Some(h) = self.line_hunk(i) {
+
+			let color = hunk_color(h.kind);
+
+			// a deletion's a line under this one, or over it if it's at the top
+			let (top, bottom) = match h.kind {
+				HunkKind::Deleted if h.new.0 == 0 => (y - padding - 1.0, y - padding + 1.0),
+				HunkKind::Deleted => (y + FONT_SIZE + padding - 1.0, y + FONT_SIZE + padding + 1.0),
+				_ => (y - padding, y + FONT_SIZE + padding),
+			};
+
+			gfx.draw(
+				&shapes::rect(
+					vec2!(0, -top),
+					vec2!(GUTTER_MARKER_WIDTH * if h.kind == HunkKind::Deleted { 2.0 } else { 1.0 }, -bottom),
+				)
+					.fill(color)
+			)?;
+
+		}
+
+		let num = match self.conf.line_numbers {
+			LineNumbers::Off => return Ok(()),
+			LineNumbers::Absolute => i + 1,
+			LineNumbers::Relative if i == cur => i + 1,
+			LineNumbers::Relative => if i > cur { i - cur } else { cur - i },
+		};
+
+		let num = num.to_string();
+		let width = self.gutter_width(cell);
+		let alpha = if i == cur { 0.8 } else { 0.3 };
+
+		gfx.draw_t(
+			mat4!()
+				.t2(vec2!(width - GUTTER_PADDING - num.len() as f32 * cell, -y))
+				,
+			&shapes::text(&num)
+				.align(gfx::Origin::TopLeft)
+				.size(FONT_SIZE)
+				.color(rgba!(1, 1, 1, alpha))
+		)?;
+
+		return Ok(());
+
+	}
+
+}
@@ -0,0 +1,255 @@
+// wengwengweng
+
+use syntect::parsing::Scope;
+use syntect::parsing::ScopeStackOp;
+use syntect::easy::ScopeRegionIterator;
+
+use super::*;
+
+// max lines to parse in a single frame, the rest is picked up in later updates
+const HIGHLIGHT_BATCH: usize = 1000;
+
+// where brackets don't count
+static LITERAL_SCOPES: Lazy<Vec<Scope>> = Lazy::new(|| {
+	return ["string", "comment"]
+		.iter()
+		.filter_map(|s| Scope::new(s).ok())
+		.collect();
+});
+
+#[derive(Clone)]
+pub struct HighlightCtx {
+	theme: Theme,
+	syntax: SyntaxReference,
+	// state after each line
+	states: Vec<HighlightState>,
+	// char ranges in strings and comments of each line
+	literals: Vec<Vec<(usize, usize)>>,
+	// lines waiting to be parsed
+	pending: Option<Edited>,
+}
+
+impl HighlightCtx {
+	pub fn new(syntax: SyntaxReference, theme: Theme) -> Self {
+		return Self {
+			theme: theme,
+			syntax: syntax,
+			states: vec![],
+			literals: vec![],
+			pending: None,
+		};
+	}
+}
+
+#[derive(Clone, PartialEq)]
+struct HighlightState {
+	parse: syntect::parsing::ParseState,
+	highlight: syntect::highlighting::HighlightState,
+}
+
+impl HighlightState {
+
+	fn new(syntax: &SyntaxReference, theme: &Theme) -> Self {
+
+		let highlighter = Highlighter::new(theme);
+
+		return Self {
+			parse: syntect::parsing::ParseState::new(&syntax),
+			highlight: syntect::highlighting::HighlightState::new(&highlighter, ScopeStack::new()),
+		};
+
+	}
+
+}
+
+// char ranges of a line inside string or comment scopes, `stack` is the scopes the line starts in
+fn literal_ranges(line: &str, ops: &[(usize, ScopeStackOp)], mut stack: ScopeStack) -> Vec<(usize, usize)> {
+
+	let mut ranges: Vec<(usize, usize)> = vec![];
+	let mut ci = 0;
+
+	for (s, op) in ScopeRegionIterator::new(ops, line) {
+
+		stack.apply(op);
+
+		let len = s.chars().count();
+
+		if len == 0 {
+			continue;
+		}
+
+		let literal = stack
+			.as_slice()
+			.iter()
+			.any(|sc| LITERAL_SCOPES.iter().any(|l| l.is_prefix_of(*sc)));
+
+		if literal {
+			match ranges.last_mut() {
+				Some(r) if r.1 == ci => r.1 = ci + len,
+				_ => ranges.push((ci, ci + len)),
+			}
+		}
+
+		ci += len;
+
+	}
+
+	return ranges;
+
+}
+
+fn plain_line(text: String) -> RenderedLine {
+	return vec![TextChunk {
+		color: rgba!(1),
+		text: text,
+	}];
+}
+
+impl TextEditor {
+
+	/// If a char is in a string or comment, as far as highlighting knows.
+	pub(super) fn in_literal(&self, i: usize, ci: usize) -> bool {
+		return self.highlight_ctx
+			.as_ref()
+			.and_then(|ctx| ctx.literals.get(i))
+			.map(|r| r.iter().any(|(s, e)| ci >= *s && ci < *e))
+			.unwrap_or(false);
+	}
+
+	/// Re-render everything from scratch.
+	pub(super) fn reset_highlight(&mut self) {
+
+		self.text.take_edited();
+		self.count_words();
+		self.rendered_lines = self.text.lines().map(plain_line).collect();
+		self.wrap_rows.clear();
+		self.rewrap(0, 0, self.text.len_lines());
+		self.recount_rows(0);
+
+		let len = self.text.len_lines();
+
+		if let Some(ctx) = &mut self.highlight_ctx {
+			ctx.states = vec![HighlightState::new(&ctx.syntax, &ctx.theme); len];
+			ctx.literals = vec![vec![]; len];
+			ctx.pending = Some(Edited {
+				first: 0,
+				tail: 0,
+			});
+		}
+
+		self.highlight_step();
+
+	}
+
+	/// Re-render the lines edited since last call.
+	pub(super) fn highlight(&mut self) {
+
+		if let Some(edited) = self.text.take_edited() {
+
+			let len = self.text.len_lines();
+			let old_len = self.rendered_lines.len();
+			let first = edited.first.min(old_len).min(len);
+			let old_end = old_len.saturating_sub(edited.tail).max(first);
+			let new_end = len.saturating_sub(edited.tail).max(first);
+
+			// while the old lines are still there
+			self.words_changed(first, old_end, new_end);
+
+			let text = &self.text;
+
+			self.rendered_lines.splice(first..old_end, (first..new_end).map(|i| {
+				return plain_line(text.line(i).unwrap_or(String::new()));
+			}));
+
+			self.rewrap(first, old_end, new_end);
+			self.shift_folds(first, old_end, new_end);
+			self.git_changed();
+
+			if let Some(ctx) = &mut self.highlight_ctx {
+
+				let init = HighlightState::new(&ctx.syntax, &ctx.theme);
+
+				ctx.states.splice(first..old_end, (first..new_end).map(|_| init.clone()));
+				ctx.literals.splice(first..old_end, (first..new_end).map(|_| vec![]));
+
+				let edited = Edited {
+					first: first,
+					tail: edited.tail,
+				};
+
+				ctx.pending = Some(match ctx.pending {
+					Some(p) => p.merge(edited),
+					None => edited,
+				});
+
+			}
+
+		}
+
+		self.highlight_step();
+
+	}
+
+	// parse pending lines until the state after a line matches the cached one again
+	fn highlight_step(&mut self) {
+
+		let ctx = match &mut self.highlight_ctx {
+			Some(ctx) => ctx,
+			None => return,
+		};
+
+		let pending = match ctx.pending.take() {
+			Some(p) => p,
+			None => return,
+		};
+
+		let len = self.text.len_lines().min(ctx.states.len());
+		let stop = len.saturating_sub(pending.tail);
+		let highlighter = Highlighter::new(&ctx.theme);
+
+		let mut state = match pending.first.checked_sub(1).and_then(|i| ctx.states.get(i)) {
+			Some(s) => s.clone(),
+			None => HighlightState::new(&ctx.syntax, &ctx.theme),
+		};
+
+		for i in pending.first..len {
+
+			if i - pending.first >= HIGHLIGHT_BATCH {
+				ctx.pending = Some(Edited {
+					first: i,
+					tail: pending.tail,
+				});
+				return;
+			}
+
+			let l = self.text.line(i).unwrap_or(String::new());
+			let ops = state.parse.parse_line(&l, &SYNTAX_SET);
+
+			ctx.literals[i] = literal_ranges(&l, &ops, state.highlight.path.clone());
+
+			let iter = HighlightIterator::new(&mut state.highlight, &ops, &l, &highlighter);
+
+			self.rendered_lines[i] = iter.map(|(s, text)| {
+				return TextChunk {
+					text: text.to_string(),
+					color: rgba!(
+						s.foreground.r as f32 / 255.0,
+						s.foreground.g as f32 / 255.0,
+						s.foreground.b as f32 / 255.0,
+						s.foreground.a as f32 / 255.0,
+					),
+				};
+			}).collect();
+
+			// the rest of the file parses the same as before
+			if i >= stop && ctx.states[i] == state {
+				return;
+			}
+
+			ctx.states[i] = state.clone();
+
+		}
+
+	}
+
+}
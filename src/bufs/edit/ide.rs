@@ -0,0 +1,450 @@
+// wengwengweng
+
+use serde_json::json;
+
+use super::*;
+
+// lines of hover text shown at once
+const HOVER_LINES: usize = 12;
+
+/// A diagnostic from the language server, in editor positions.
+#[derive(Clone, Debug, PartialEq)]
+pub struct Diag {
+	pub start: Cursor,
+	pub end: Cursor,
+	pub severity: Severity,
+	pub message: String,
+}
+
+/// What the language server knows about this document.
+#[derive(Clone, Debug)]
+pub struct IdeState {
+	version: i64,
+	pub diags: Vec<Diag>,
+	pub hover: Option<String>,
+}
+
+impl IdeState {
+	pub fn new() -> Self {
+		return Self {
+			version: 0,
+			diags: vec![],
+			hover: None,
+		};
+	}
+}
+
+pub fn severity_color(s: Severity) -> Color {
+	return match s {
+		Severity::Error => rgba!(1, 0.3, 0.3, 1),
+		Severity::Warning => rgba!(1, 0.8, 0.3, 1),
+		Severity::Info => rgba!(0.5, 0.8, 1, 1),
+		Severity::Hint => rgba!(0.7, 0.7, 0.7, 1),
+	};
+}
+
+impl TextEditor {
+
+	fn to_lsp_pos(&self, pos: Cursor) -> Pos {
+
+		let line = self.get_line_at(pos.line).unwrap_or(String::new());
+
+		let character = graphemes(&line)
+			.take((pos.col - 1).max(0) as usize)
+			.map(|g| g.encode_utf16().count())
+			.sum();
+
+		return Pos {
+			line: (pos.line - 1).max(0) as usize,
+			character: character,
+		};
+
+	}
+
+	fn from_lsp_pos(&self, pos: Pos) -> Cursor {
+
+		let line = self.get_line_at(pos.line as Line + 1).unwrap_or(String::new());
+		let mut units = 0;
+		let mut col = 0;
+
+		for g in graphemes(&line) {
+			if units >= pos.character {
+				break;
+			}
+			units += g.encode_utf16().count();
+			col += 1;
+		}
+
+		return self.clamp_cursor(Cursor::new(pos.line as Line + 1, col + 1));
+
+	}
+
+	// scratch buffers aren't documents a server knows about
+	fn lsp_lang(&self) -> Option<&String> {
+		if self.scratch {
+			return None;
+		}
+		return self.lang.as_ref();
+	}
+
+	/// Tell the server about the document, if there's one for the language.
+	pub(super) fn lsp_open(&mut self) {
+		// it gets the whole text, which has the edits not sent yet
+		self.text.take_changes();
+		if let Some(lang) = self.lsp_lang() {
+			self.lsp.borrow_mut().open(lang, &self.path, &self.content());
+		}
+	}
+
+	pub(super) fn lsp_save(&mut self) {
+		if let Some(lang) = self.lsp_lang() {
+			self.lsp.borrow_mut().save(lang, &self.path);
+		}
+	}
+
+	pub(super) fn lsp_close(&mut self) {
+		if let Some(lang) = self.lsp_lang() {
+			self.lsp.borrow_mut().close(lang, &self.path);
+		}
+	}
+
+	// send the edits, before anything that asks about the text, as ranges if the server takes them or the whole text if not
+	fn sync_lsp(&mut self) {
+
+		let changes = self.text.take_changes();
+
+		if changes.is_empty() {
+			return;
+		}
+
+		let lang = match self.lsp_lang() {
+			Some(lang) => lang.clone(),
+			None => return,
+		};
+
+		let changes = if self.lsp.borrow().incremental(&lang) {
+			changes
+				.into_iter()
+				.map(|c| {
+					return ContentChange {
+						range: Some(Range {
+							start: Pos {
+								line: c.start.0,
+								character: c.start.1,
+							},
+							end: Pos {
+								line: c.end.0,
+								character: c.end.1,
+							},
+						}),
+						text: c.text,
+					};
+				})
+				.collect()
+		} else {
+			vec![ContentChange {
+				range: None,
+				text: self.content(),
+			}]
+		};
+
+		self.ide.version += 1;
+		self.lsp.borrow_mut().change(&lang, &self.path, self.ide.version, changes);
+
+	}
+
+	fn lsp_request(&mut self, req: Req, extra: serde_json::Value) {
+
+		self.sync_lsp();
+
+		let lang = match self.lsp_lang() {
+			Some(lang) => lang.clone(),
+			None => {
+				self.log.push(Msg::error("no language server"));
+				return;
+			},
+		};
+
+		let pos = self.to_lsp_pos(self.cursor);
+
+		if let Err(e) = self.lsp.borrow_mut().request(&lang, &self.path, req, pos, extra) {
+			self.log.push(Msg::error(&e));
+		}
+
+	}
+
+	pub(super) fn request_completion(&mut self) {
+		self.lsp_request(Req::Completion, json!({}));
+	}
+
+	pub(super) fn request_hover(&mut self) {
+		self.lsp_request(Req::Hover, json!({}));
+	}
+
+	pub(super) fn goto_definition(&mut self) {
+		self.lsp_request(Req::Definition, json!({}));
+	}
+
+	pub(super) fn find_references(&mut self) {
+		self.lsp_request(Req::References, json!({
+			"context": {
+				"includeDeclaration": true,
+			},
+		}));
+	}
+
+	pub(super) fn rename(&mut self, name: &str) {
+		self.lsp_request(Req::Rename, json!({
+			"newName": name,
+		}));
+	}
+
+	pub(super) fn has_lsp(&self) -> bool {
+		return match self.lsp_lang() {
+			Some(lang) => self.lsp.borrow().has_server(lang),
+			None => false,
+		};
+	}
+
+	/// If a char typed in insert mode is one the server wants to complete after.
+	pub(super) fn is_trigger_char(&self, ch: char) -> bool {
+		return match self.lsp_lang() {
+			Some(lang) => self.lsp.borrow().trigger_chars(lang).contains(&ch),
+			None => false,
+		};
+	}
+
+	/// Sync edits and handle what the server sent for this document.
+	pub(super) fn update_lsp(&mut self) {
+
+		self.sync_lsp();
+
+		if self.scratch {
+			return;
+		}
+
+		let events = self.lsp.borrow_mut().take_events(&self.path);
+
+		for e in events {
+			self.handle_lsp_event(e);
+		}
+
+		// like edits from a rename
+		self.sync_lsp();
+
+	}
+
+	fn handle_lsp_event(&mut self, e: LspEvent) {
+
+		match e {
+
+			LspEvent::Diagnostics(diags) => {
+				self.ide.diags = diags
+					.into_iter()
+					.map(|d| {
+						return Diag {
+							start: self.from_lsp_pos(d.range.start),
+							end: self.from_lsp_pos(d.range.end),
+							severity: d.severity,
+							message: d.message,
+						};
+					})
+					.collect();
+				self.ide.diags.sort_by_key(|d| d.start);
+			},
+
+			LspEvent::Completion(items) => {
+				let items = items
+					.into_iter()
+					.map(|i| {
+						return CompleteItem {
+							label: i.label,
+							detail: i.detail,
+							insert: i.insert,
+							replace: i.range.map(|r| (self.from_lsp_pos(r.start), self.from_lsp_pos(r.end))),
+						};
+					})
+					.collect();
+				// nothing from the server, words are better than nothing
+				if !self.show_completion(self.word_start(), items, false) {
+					self.complete_words(false);
+				}
+			},
+
+			LspEvent::Hover(text) => {
+				if text.trim().is_empty() {
+					self.log.push(Msg::info("no info"));
+				} else {
+					self.ide.hover = Some(text);
+				}
+			},
+
+			LspEvent::Definition(locs) => {
+				match locs.first() {
+					Some(loc) => self.jump_location(loc.clone()),
+					None => self.log.push(Msg::error("no definition found")),
+				}
+			},
+
+			LspEvent::References(locs) => {
+				match locs.len() {
+					0 => self.log.push(Msg::error("no references found")),
+					1 => self.jump_location(locs[0].clone()),
+					_ => {
+						if let Err(e) = self.list_locations(&locs) {
+							self.log.push(Msg::error(&e));
+						}
+					},
+				}
+			},
+
+			LspEvent::Edits(edits) => self.apply_edits(edits),
+
+			// the edits are already in the other files, opened if they weren't
+			LspEvent::Renamed(paths) => {
+				self.log.push(Msg::success(&format!("renamed in {} files, not saved yet", paths.len() + 1)));
+			},
+
+			LspEvent::Goto(pos) => self.move_to(self.from_lsp_pos(pos)),
+
+		}
+
+	}
+
+	fn jump_location(&mut self, loc: Location) {
+
+		let same = loc.path.canonicalize().ok() == self.path.canonicalize().ok();
+
+		if same {
+			self.move_to(self.from_lsp_pos(loc.range.start));
+		} else {
+			self.lsp.borrow_mut().push_event_for(&loc.path, LspEvent::Goto(loc.range.start));
+			self.actions.push(Action::Open(loc.path));
+		}
+
+	}
+
+	// list "path:line:col: text" for each location in a scratch buffer
+	fn list_locations(&mut self, locs: &[Location]) -> Result<()> {
+
+		let mut out = String::new();
+
+		for loc in locs {
+
+			let line = std::fs::read_to_string(&loc.path)
+				.ok()
+				.and_then(|c| c.lines().nth(loc.range.start.line).map(String::from))
+				.unwrap_or(String::new());
+
+			out.push_str(&format!(
+				"{}:{}:{}: {}\n",
+				loc.path.display(),
+				loc.range.start.line + 1,
+				loc.range.start.character + 1,
+				line.trim(),
+			));
+
+		}
+
+		let name = self.path
+			.file_name()
+			.map(|n| n.to_string_lossy().to_string())
+			.unwrap_or(String::new());
+
+		self.actions.push(Action::Scratch(self.path.with_file_name(format!("{}.refs", name)), out));
+
+		return Ok(());
+
+	}
+
+	/// Apply edits from the server as one undo step, they all refer to the text before any of them.
+	fn apply_edits(&mut self, mut edits: Vec<TextEdit>) {
+
+		if edits.is_empty() {
+			return;
+		}
+
+		// last first so earlier positions stay valid
+		edits.sort_by(|a, b| (b.range.start.line, b.range.start.character).cmp(&(a.range.start.line, a.range.start.character)));
+
+		self.commit();
+
+		for e in edits {
+			let s = self.pos_to_idx(self.from_lsp_pos(e.range.start));
+			let end = self.pos_to_idx(self.from_lsp_pos(e.range.end));
+			self.text.remove(s, end);
+			self.text.insert(s, &e.text);
+		}
+
+		self.commit();
+		self.move_to(self.cursor);
+		self.highlight();
+
+	}
+
+	/// Jump to the next diagnostic after the cursor, wrapping around.
+	pub(super) fn next_diag(&mut self) {
+
+		let diag = self.ide.diags
+			.iter()
+			.find(|d| d.start > self.cursor)
+			.or(self.ide.diags.first())
+			.cloned();
+
+		match diag {
+			Some(d) => {
+				self.move_to(d.start);
+				self.log.push(Msg::info(&d.message));
+			},
+			None => self.log.push(Msg::info("no diagnostics")),
+		}
+
+	}
+
+	/// The most severe diagnostic on a line.
+	pub(super) fn line_diag(&self, ln: Line) -> Option<&Diag> {
+		return self.ide.diags
+			.iter()
+			.filter(|d| d.start.line <= ln && d.end.line >= ln)
+			.min_by_key(|d| d.severity);
+	}
+
+	pub(super) fn draw_hover(&self, gfx: &mut Gfx, x: f32, y: f32) -> Result<()> {
+
+		let text = match &self.ide.hover {
+			Some(t) => t,
+			None => return Ok(()),
+		};
+
+		let lines = text
+			.lines()
+			.take(HOVER_LINES)
+			.collect::<Vec<&str>>();
+
+		let w = lines.iter().map(|l| width(l)).max().unwrap_or(0) as f32 * self.wrap.cell + LINE_SPACING * 2.0;
+		let h = LINE_HEIGHT * lines.len() as f32;
+
+		gfx.draw(
+			&shapes::rect(
+				vec2!(x, -y),
+				vec2!(x + w, -y - h),
+			)
+				.fill(rgba!(0.1, 0.1, 0.15, 0.95))
+		)?;
+
+		for (n, l) in lines.iter().enumerate() {
+			gfx.draw_t(
+				mat4!()
+					.t2(vec2!(x + LINE_SPACING, -y - n as f32 * LINE_HEIGHT - LINE_SPACING * 0.5))
+					,
+				&shapes::text(l)
+					.align(gfx::Origin::TopLeft)
+					.size(FONT_SIZE)
+			)?;
+		}
+
+		return Ok(());
+
+	}
+
+}
@@ -0,0 +1,88 @@
+// wengwengweng
+
+use std::collections::BTreeMap;
+use serde::Serialize;
+use serde::Deserialize;
+
+use super::*;
+
+const MACROS_ENTRY: &str = "macros";
+
+/// Recorded commands in named slots, shared by all text editors and kept on disk.
+#[derive(Serialize, Deserialize, Default)]
+pub struct Macros {
+	slots: BTreeMap<char, Vec<Command>>,
+}
+
+impl Macros {
+
+	pub fn load() -> Self {
+		return data::load(PROJ, MACROS_ENTRY).unwrap_or_default();
+	}
+
+	fn save(&self) -> Result<()> {
+		return data::save(PROJ, MACROS_ENTRY, self);
+	}
+
+	fn get(&self, slot: char) -> Option<&Vec<Command>> {
+		return self.slots.get(&slot);
+	}
+
+	fn set(&mut self, slot: char, cmds: Vec<Command>) -> Result<()> {
+		self.slots.insert(slot, cmds);
+		return self.save();
+	}
+
+}
+
+impl TextEditor {
+
+	pub(super) fn start_record(&mut self, slot: char) {
+		self.recording = Some(slot);
+		self.record.clear();
+	}
+
+	pub(super) fn stop_record(&mut self) {
+
+		if let Some(slot) = self.recording.take() {
+
+			let record = std::mem::replace(&mut self.record, vec![]);
+
+			if let Err(e) = self.macros.borrow_mut().set(slot, record) {
+				self.log.push(Msg::error(&e));
+			}
+
+			self.last_macro = Some(slot);
+
+		}
+
+	}
+
+	/// Run a macro `n` times, as a single undo step.
+	pub(super) fn replay(&mut self, slot: char, n: usize) {
+
+		let cmds = match self.macros.borrow().get(slot) {
+			Some(cmds) => cmds.clone(),
+			None => {
+				self.log.push(Msg::error(&format!("no macro in '{}'", slot)));
+				return;
+			},
+		};
+
+		self.commit();
+		self.replaying = true;
+
+		for _ in 0..n {
+			for cmd in &cmds {
+				self.exec(*cmd);
+			}
+		}
+
+		self.replaying = false;
+		self.commit();
+		self.last_macro = Some(slot);
+		self.highlight();
+
+	}
+
+}
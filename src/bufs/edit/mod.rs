@@ -0,0 +1,2078 @@
+// wengwengweng
+
+// TODO: clean up
+
+mod text;
+use text::*;
+mod select;
+use select::*;
+mod register;
+pub use register::*;
+mod yank;
+use yank::*;
+mod highlight;
+use highlight::*;
+mod undo;
+use undo::*;
+mod substitute;
+use substitute::*;
+mod ex;
+mod macros;
+mod cursors;
+mod comment;
+mod config;
+use config::*;
+mod encoding;
+use encoding::*;
+pub use encoding::is_binary;
+mod watch;
+use watch::*;
+pub use watch::DiskState;
+mod swap;
+use swap::*;
+mod wrap;
+use wrap::*;
+mod fold;
+mod brackets;
+mod complete;
+use complete::*;
+mod snippet;
+use snippet::*;
+mod git;
+use git::*;
+mod ide;
+use ide::*;
+pub use swap::swapped_files;
+pub use undo::prune_undo;
+pub use macros::Macros;
+pub use complete::WordIndex;
+pub use snippet::Snippets;
+
+use std::fmt;
+use std::io;
+use std::path::Path;
+use std::path::PathBuf;
+use std::rc::Rc;
+use std::cell::RefCell;
+use std::time::Instant;
+use std::time::Duration;
+use serde::Serialize;
+use serde::Deserialize;
+use std::collections::HashSet;
+use std::collections::BTreeMap;
+use std::collections::HashMap;
+
+use crate::*;
+use kit::textinput::*;
+
+use once_cell::sync::Lazy;
+use syntect::parsing::SyntaxSet;
+use syntect::parsing::SyntaxReference;
+use syntect::parsing::ScopeStack;
+use syntect::highlighting::ThemeSet;
+use syntect::highlighting::Theme;
+use syntect::highlighting::Highlighter;
+use syntect::highlighting::HighlightIterator;
+
+type Line = i32;
+type Col = i32;
+
+const LINE_SPACING: f32 = 3.0;
+const FONT_SIZE: f32 = 12.0;
+const LINE_HEIGHT: f32 = FONT_SIZE + LINE_SPACING;
+const CURSOR_WIDTH: f32 = 12.0;
+const TAB_WIDTH: usize = 4;
+// seconds to travel in undo history
+const TIME_STEP: u64 = 30;
+
+static SYNTAX_SET: Lazy<SyntaxSet> = Lazy::new(|| {
+	return syntect::dumps::from_binary(include_bytes!("../syntaxset.pack"));
+});
+
+static WRAP_CHARS: Lazy<HashMap<char, char>> = Lazy::new(|| {
+	return hmap![
+		'(' => ')',
+		'[' => ']',
+		'{' => '}',
+		'"' => '"',
+		'\'' => '\'',
+	];
+});
+
+static SCOPE_CHARS: Lazy<HashMap<char, char>> = Lazy::new(|| {
+	return hmap![
+		'(' => ')',
+		'[' => ']',
+		'{' => '}',
+	];
+});
+
+static BREAK_CHARS: Lazy<HashSet<char>> = Lazy::new(|| {
+	return hset![' ', ',', '.', ';', ':', '"', '(', ')', '{', '}', '[', ']', '<', '>', '_', '-', '@', '/', '\\', '\'', '\t' ];
+});
+
+fn is_break(g: &str) -> bool {
+	let mut chars = g.chars();
+	return match (chars.next(), chars.next()) {
+		(Some(ch), None) => BREAK_CHARS.contains(&ch),
+		_ => false,
+	};
+}
+
+// col counts graphemes, not bytes or chars
+#[derive(Clone, Copy, Debug, PartialEq, Eq, PartialOrd, Ord, Serialize, Deserialize)]
+pub struct Cursor {
+	pub line: Line,
+	pub col: Col,
+}
+
+impl Cursor {
+	fn new(l: Line, c: Col) -> Self {
+		return Self {
+			line: l,
+			col: c,
+		};
+	}
+}
+
+impl fmt::Display for Cursor {
+	fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+		return write!(f, "{}:{}", self.line, self.col);
+	}
+}
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+enum Mode {
+	Normal,
+	Insert,
+	Select,
+	Command,
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Serialize, Deserialize)]
+enum Command {
+	Insert(char),
+	MoveTo(Cursor),
+	MoveUp,
+	MoveDown,
+	MoveLeft,
+	MoveRight,
+	MovePrevWord,
+	MoveNextWord,
+	MoveLineStart,
+	MoveLineEnd,
+	DelLine,
+	DelWord,
+	Del,
+	Undo,
+	Redo,
+	BreakLine,
+	BreakLineIndent,
+	DelPair,
+}
+
+impl Command {
+	// commands that apply at every cursor
+	fn multi(&self) -> bool {
+		return match self {
+			Command::MoveTo(_)
+			| Command::DelLine
+			| Command::Undo
+			| Command::Redo
+			=> false,
+			_ => true,
+		};
+	}
+}
+
+// waiting for a char to complete a command
+#[derive(Clone, Copy, Debug, PartialEq)]
+enum Pending {
+	Replace,
+	Register,
+	Record,
+	Replay,
+}
+
+pub struct TextEditor {
+	text: Text,
+	cursor: Cursor,
+	// other cursors edits also apply at
+	cursors: Vec<Cursor>,
+	undo: UndoTree,
+	// the file on disk as last read or written, to tell if saved undo history still applies and if someone else changed it
+	disk: DiskState,
+	conflict: Option<Conflict>,
+	swap: SwapState,
+	// copy the file to the backup dir before saving
+	backup: bool,
+	// line ending, charset and final newline the file has on disk
+	disk_format: String,
+	path: PathBuf,
+	// not backed by a file, like a diff, `path` is only where it'd be saved and nothing about it is kept
+	scratch: bool,
+	rendered_lines: Vec<RenderedLine>,
+	wrap: WrapState,
+	// where each display row starts in each line, when wrapping
+	wrap_rows: Vec<Vec<usize>>,
+	// display row each line starts at and one more for the end, lines in closed folds take none
+	row_starts: Vec<usize>,
+	// closed folds, first and last line, the first stays visible
+	folds: Vec<(usize, usize)>,
+	mode: Mode,
+	scroll_off: f32,
+	scroll_remainder: f32,
+	view_size: Option<(f32, f32)>,
+	recording: Option<char>,
+	record: Vec<Command>,
+	macros: Rc<RefCell<Macros>>,
+	last_macro: Option<char>,
+	replaying: bool,
+	count: Option<usize>,
+	highlight_ctx: Option<HighlightCtx>,
+	// name of the syntax
+	lang: Option<String>,
+	cmd_bar: Input,
+	search_pattern: Option<regex::Regex>,
+	selection: Selection,
+	pending: Option<Pending>,
+	registers: Rc<RefCell<Registers>>,
+	reg: Option<char>,
+	last_put: Option<LastPut>,
+	// lines the command bar was opened on from select mode
+	cmd_range: Option<(Line, Line)>,
+	confirm: Option<Confirm>,
+	log: Vec<Msg>,
+	actions: Vec<Action>,
+	conf: FileConf,
+	lsp: Rc<RefCell<Lsp>>,
+	ide: IdeState,
+	completion: Option<Completion>,
+	words: Rc<RefCell<WordIndex>>,
+	// words in this buffer and how many times, kept up with edits
+	word_counts: BTreeMap<String, usize>,
+	// edited since the words were last published
+	words_dirty: Option<Instant>,
+	// last typed in insert mode, to complete after a pause
+	typed: Option<Instant>,
+	complete_delay: Option<Duration>,
+	snippets: Rc<Snippets>,
+	snippet: Option<SnippetState>,
+	git: GitState,
+}
+
+/// State shared by all text editors.
+#[derive(Clone)]
+pub struct Shared {
+	pub registers: Rc<RefCell<Registers>>,
+	pub macros: Rc<RefCell<Macros>>,
+	// editorconfig style properties by syntax name
+	pub langs: Rc<HashMap<String, HashMap<String, String>>>,
+	pub backup: bool,
+	pub lsp: Rc<RefCell<Lsp>>,
+	pub words: Rc<RefCell<WordIndex>>,
+	// complete on its own after this long without typing
+	pub complete_delay: Option<Duration>,
+	pub snippets: Rc<Snippets>,
+}
+
+type RenderedLine = Vec<TextChunk>;
+
+#[derive(Clone)]
+struct TextChunk {
+	text: String,
+	color: Color,
+}
+
+impl TextEditor {
+
+	/// An editor for a file, empty if it doesn't exist yet.
+	///
+	/// A file that's there but can't be read is an error, an empty buffer would overwrite it on save.
+	pub fn new(path: impl AsRef<Path>, shared: &Shared) -> Result<Self> {
+
+		let path = path.as_ref();
+
+		let bytes = match std::fs::read(&path) {
+			Ok(bytes) => bytes,
+			Err(e) if e.kind() == io::ErrorKind::NotFound => vec![],
+			Err(_) => return Err(format!("failed to read {}", path.display())),
+		};
+
+		return Ok(Self::from_bytes(path, &bytes, false, shared));
+
+	}
+
+	/// A buffer with some text that's not a file until it's written somewhere with `:w <path>`.
+	pub fn scratch(path: impl AsRef<Path>, content: &str, shared: &Shared) -> Self {
+		return Self::from_bytes(path.as_ref(), content.as_bytes(), true, shared);
+	}
+
+	fn from_bytes(path: &Path, bytes: &[u8], scratch: bool, shared: &Shared) -> Self {
+
+		let disk = DiskState::new(path, &bytes);
+
+		let syntax = SYNTAX_SET
+			.find_syntax_for_file(path)
+			.ok()
+			.flatten()
+			.cloned();
+
+		let theme = ThemeSet::load_from_reader(&mut io::Cursor::new(&include_str!("../themes/dracula.tmTheme")[..])).ok();
+
+		let lang = syntax.as_ref().map(|s| s.name.clone());
+		let mut conf = FileConf::for_file(path, lang.as_deref(), &shared.langs);
+		let (content, warnings) = conf.decode_file(bytes);
+		let log = warnings
+			.iter()
+			.map(|w| Msg::error(w))
+			.collect();
+
+		let hi_ctx = match (syntax, theme) {
+			(Some(s), Some(t)) => Some(HighlightCtx::new(s, t)),
+			_ => None
+		};
+
+		let mut editor = Self {
+			text: Text::from_str(&content),
+			cursor: Cursor::new(1, 1),
+			cursors: vec![],
+			undo: if scratch {
+				UndoTree::new()
+			} else {
+				UndoTree::load(path, disk.hash).unwrap_or_else(UndoTree::new)
+			},
+			disk: disk,
+			conflict: None,
+			swap: SwapState::new(),
+			backup: shared.backup,
+			disk_format: conf.format_label(),
+			path: path.to_path_buf(),
+			scratch: scratch,
+			rendered_lines: vec![],
+			wrap: WrapState::default(),
+			wrap_rows: vec![],
+			row_starts: vec![],
+			folds: vec![],
+			mode: Mode::Normal,
+			scroll_off: 0.0,
+			scroll_remainder: 0.0,
+			view_size: None,
+			recording: None,
+			record: vec![],
+			macros: shared.macros.clone(),
+			last_macro: None,
+			replaying: false,
+			count: None,
+			highlight_ctx: hi_ctx,
+			lang: lang,
+			cmd_bar: Input::new(),
+			search_pattern: None,
+			selection: Selection {
+				anchor: Cursor::new(1, 1),
+				kind: SelectKind::Char,
+			},
+			pending: None,
+			registers: shared.registers.clone(),
+			reg: None,
+			last_put: None,
+			cmd_range: None,
+			confirm: None,
+			log: log,
+			actions: vec![],
+			conf: conf,
+			lsp: shared.lsp.clone(),
+			ide: IdeState::new(),
+			completion: None,
+			words: shared.words.clone(),
+			word_counts: bmap![],
+			words_dirty: None,
+			typed: None,
+			complete_delay: shared.complete_delay,
+			snippets: shared.snippets.clone(),
+			snippet: None,
+			git: GitState::new(),
+		};
+
+		editor.reset_highlight();
+		editor.check_swap();
+		editor.lsp_open();
+		editor.publish_words();
+		editor.git_reload();
+
+		return editor;
+
+	}
+
+	fn content(&self) -> String {
+		return self.text.to_string();
+	}
+
+	fn get_line_at(&self, ln: Line) -> Option<String> {
+		if ln > 0 {
+			return self.text.line(ln as usize - 1);
+		}
+		return None;
+	}
+
+	fn line_len_at(&self, ln: Line) -> Option<Col> {
+		if ln > 0 {
+			return self.text.line_len(ln as usize - 1).map(|l| l as Col);
+		}
+		return None;
+	}
+
+	fn cur_line(&self) -> Option<String> {
+		return self.get_line_at(self.cursor.line);
+	}
+
+	fn pos_to_idx(&self, pos: Cursor) -> usize {
+		return self.text.pos_to_char(
+			(pos.line - 1).max(0) as usize,
+			(pos.col - 1).max(0) as usize,
+		);
+	}
+
+	fn idx_to_pos(&self, idx: usize) -> Cursor {
+		let (line, col) = self.text.char_to_pos(idx);
+		return Cursor::new(line as Line + 1, col as Col + 1);
+	}
+
+	fn set_line_at(&mut self, ln: Line, content: &str) {
+
+		if let Some(len) = self.line_len_at(ln) {
+
+			let start = self.pos_to_idx(Cursor::new(ln, 1));
+
+			self.text.remove(start, start + len as usize);
+			self.text.insert(start, content);
+
+		}
+
+	}
+
+	fn set_line(&mut self, content: &str) {
+		self.set_line_at(self.cursor.line, content);
+	}
+
+	fn insert_str_at(&mut self, pos: Cursor, text: &str) -> Cursor {
+
+		if self.line_len_at(pos.line).is_some() {
+
+			let idx = self.pos_to_idx(pos);
+
+			self.text.insert(idx, text);
+
+			return self.clamp_cursor(self.idx_to_pos(idx + text.chars().count()));
+
+		}
+
+		return pos;
+
+	}
+
+	fn insert_str(&mut self, text: &str) {
+		self.cursor = self.insert_str_at(self.cursor, text);
+	}
+
+	fn insert_at(&mut self, pos: Cursor, ch: char) -> Cursor {
+
+		if self.line_len_at(pos.line).is_some() {
+
+			// undo typing a word at a time
+			if BREAK_CHARS.contains(&ch) && !self.replaying {
+				self.commit();
+			}
+
+			let idx = self.pos_to_idx(pos);
+
+			self.text.insert_char(idx, ch);
+
+			// combining chars join the previous grapheme instead of advancing
+			return self.clamp_cursor(self.idx_to_pos(idx + 1));
+
+		}
+
+		return pos;
+
+	}
+
+	fn insert(&mut self, ch: char) {
+		self.cursor = self.insert_at(self.cursor, ch);
+	}
+
+	fn del_line_at(&mut self, ln: Line) -> Line {
+
+		let lines = self.text.len_lines();
+
+		if ln >= 1 && ln as usize <= lines {
+
+			let i = ln as usize - 1;
+
+			// take the line break before the last line, since it doesn't have one after
+			let (start, end) = if i + 1 < lines {
+				(self.text.pos_to_char(i, 0), self.text.pos_to_char(i + 1, 0))
+			} else if i > 0 {
+				(self.text.pos_to_char(i, 0) - 1, self.text.len_chars())
+			} else {
+				(0, self.text.len_chars())
+			};
+
+			self.text.remove(start, end);
+
+		}
+
+		return ln.max(1).min(self.text.len_lines() as Line);
+
+	}
+
+	fn del_line(&mut self) {
+		self.yank_line();
+		self.cursor.line = self.del_line_at(self.cursor.line);
+	}
+
+	fn char_at(&self, pos: Cursor) -> Option<char> {
+		if pos.line < 1 || pos.col < 1 {
+			return None;
+		}
+		return self.text.char_at(pos.line as usize - 1, pos.col as usize - 1);
+	}
+
+	fn cur_char(&self) -> Option<char> {
+		return self.char_at(self.cursor);
+	}
+
+	fn grapheme_at(&self, pos: Cursor) -> Option<String> {
+		if pos.line < 1 || pos.col < 1 {
+			return None;
+		}
+		return self.text.grapheme_at(pos.line as usize - 1, pos.col as usize - 1);
+	}
+
+	fn cur_grapheme(&self) -> Option<String> {
+		return self.grapheme_at(self.cursor);
+	}
+
+	fn insert_line_at(&mut self, line: Line) {
+
+		if line < 1 {
+			self.text.insert(0, "\n");
+		} else if let Some(len) = self.line_len_at(line) {
+			let idx = self.pos_to_idx(Cursor::new(line, len + 1));
+			self.text.insert(idx, "\n");
+		}
+
+	}
+
+	fn insert_line(&mut self) {
+		self.insert_line_at(self.cursor.line);
+	}
+
+	fn break_line_at(&mut self, mut pos: Cursor) -> Cursor {
+
+		if self.line_len_at(pos.line).is_some() {
+
+			let idx = self.pos_to_idx(pos);
+
+			self.text.insert_char(idx, '\n');
+
+			pos.line += 1;
+			pos.col = 1;
+
+			return self.clamp_cursor(pos);
+
+		}
+
+		return pos;
+
+	}
+
+	fn break_line(&mut self) {
+		self.cursor = self.break_line_at(self.cursor);
+	}
+
+	// break the line indented like it, one more after an opening bracket, and put the closing one on a line of its own
+	fn break_line_indent(&mut self) {
+
+		let line = self.cur_line();
+		let cursor = self.cursor;
+
+		self.break_line();
+
+		let mut level = 0;
+
+		if let Some(cur_line) = line {
+
+			level = self.conf.indent_level(&cur_line);
+
+			let mut chars = graphemes(&cur_line)
+				.skip((cursor.col - 2) as usize)
+				.filter_map(|g| g.chars().next());
+
+			if let Some(ch) = chars.next() {
+				if let Some(wch) = SCOPE_CHARS.get(&ch) {
+					level += 1;
+					if Some(*wch) == chars.next() {
+						let indent = self.conf.indent_unit().repeat(level - 1);
+						self.break_line();
+						self.insert_str(&indent);
+						self.move_up();
+					}
+				}
+			}
+
+		}
+
+		let indent = self.conf.indent_unit().repeat(level);
+
+		self.insert_str(&indent);
+
+	}
+
+	// join a line with the previous line
+	fn join_line_at(&mut self, mut pos: Cursor) -> Cursor {
+
+		if let Some(prev_len) = self.line_len_at(pos.line - 1) {
+
+			let idx = self.pos_to_idx(Cursor::new(pos.line, 1));
+
+			self.text.remove(idx - 1, idx);
+
+			pos.line -= 1;
+			pos.col = prev_len + 1;
+
+		}
+
+		return pos;
+
+	}
+
+	fn del_at(&mut self, mut pos: Cursor) -> Cursor {
+
+		if self.line_len_at(pos.line).is_some() {
+
+			if pos.col <= 1 {
+				return self.join_line_at(pos);
+			}
+
+			let prev = Cursor {
+				col: pos.col - 1,
+				.. pos
+			};
+
+			let start = self.pos_to_idx(prev);
+			let end = self.pos_to_idx(pos);
+
+			self.text.remove(start, end);
+			pos = prev;
+
+		}
+
+		return pos;
+
+	}
+
+	fn del(&mut self) {
+		self.cursor = self.del_at(self.cursor);
+	}
+
+	// delete the char before, and the one after too if it's the other half of a pair
+	fn del_pair(&mut self) {
+
+		if let Some(cur_char) = self.cur_char() {
+			if let Some(_) = WRAP_CHARS.get(&cur_char) {
+				self.move_right();
+				self.del();
+			}
+		}
+
+		self.del();
+
+	}
+
+	fn del_word_at(&mut self, pos: Cursor) -> Cursor {
+
+		if self.line_len_at(pos.line).is_some() {
+
+			if pos.col <= 1 {
+				return self.join_line_at(pos);
+			} else if let Some(prev_pos) = self.prev_word_at(pos) {
+				return self.del_range((prev_pos, Cursor {
+					col: pos.col - 1,
+					.. pos
+				}));
+			}
+
+		}
+
+		return pos;
+
+	}
+
+	fn del_word(&mut self) {
+		let pos = self.del_word_at(self.cursor);
+		self.move_to(pos);
+	}
+
+	// end is inclusive, and can span multiple lines
+	fn del_range(&mut self, r: (Cursor, Cursor)) -> Cursor {
+
+		let (start, end) = r;
+
+		if self.line_len_at(start.line).is_some() && self.line_len_at(end.line).is_some() {
+
+			let start_idx = self.pos_to_idx(start);
+			let end_idx = self.pos_to_idx(Cursor {
+				col: end.col + 1,
+				.. end
+			});
+
+			if start_idx < end_idx {
+				self.text.remove(start_idx, end_idx);
+			}
+
+			return self.clamp_cursor(start);
+
+		}
+
+		return self.cursor;
+
+	}
+
+	fn clamp_cursor(&self, mut pos: Cursor) -> Cursor {
+
+		if pos.col < 1 {
+			pos.col = 1;
+		}
+
+		if pos.line < 1 {
+			pos.line = 1;
+		}
+
+		if pos.line > self.text.len_lines() as Line {
+			pos.line = self.text.len_lines() as Line;
+		}
+
+		if let Some(len) = self.line_len_at(pos.line) {
+
+			let len = len + 1;
+
+			if pos.col > len {
+				pos.col = len;
+			}
+
+		}
+
+		return pos;
+
+	}
+
+	fn move_to(&mut self, pos: Cursor) {
+		self.cursor = self.clamp_cursor(pos);
+	}
+
+	fn move_left(&mut self) {
+		self.move_to(Cursor {
+			col: self.cursor.col - 1,
+			.. self.cursor
+		});
+	}
+
+	fn move_right(&mut self) {
+		self.move_to(Cursor {
+			col: self.cursor.col + 1,
+			.. self.cursor
+		});
+	}
+
+	fn move_up(&mut self) {
+		if self.move_row(false) {
+			return;
+		}
+		self.move_to(Cursor {
+			line: self.cursor.line - 1,
+			.. self.cursor
+		});
+	}
+
+	fn move_down(&mut self) {
+		if self.move_row(true) {
+			return;
+		}
+		self.move_to(Cursor {
+			line: self.cursor.line + 1,
+			.. self.cursor
+		});
+	}
+
+	fn move_prev_word(&mut self) {
+		if let Some(pos) = self.prev_word() {
+			self.move_to(pos);
+		}
+	}
+
+	fn move_next_word(&mut self) {
+		if let Some(pos) = self.next_word() {
+			self.move_to(pos);
+		}
+	}
+
+	fn next_word_at(&self, pos: Cursor) -> Option<Cursor> {
+
+		let line = self.get_line_at(pos.line)?;
+		let len = graphemes(&line).count() as Col;
+
+		if pos.col < len {
+
+			for (i, g) in graphemes(&line).enumerate().skip(pos.col as usize) {
+
+				if is_break(g) {
+					return Some(Cursor {
+						col: i as Col + 1,
+						.. pos
+					});
+				}
+
+			}
+
+			return Some(Cursor {
+				col: len + 1,
+				.. pos
+			});
+
+		}
+
+		return None;
+
+	}
+
+	fn next_word(&self) -> Option<Cursor> {
+		return self.next_word_at(self.cursor);
+	}
+
+	fn prev_word_at(&self, pos: Cursor) -> Option<Cursor> {
+
+		let line = self.get_line_at(pos.line)?;
+		let gs = graphemes(&line).collect::<Vec<&str>>();
+
+		if pos.col <= gs.len() as Col + 1 {
+
+			let end = (pos.col - 2).max(0).min(gs.len() as Col);
+
+			for (i, g) in gs[..end as usize].iter().enumerate().rev() {
+
+				if is_break(g) {
+					return Some(Cursor {
+						col: i as Col + 2,
+						.. pos
+					});
+				}
+
+			}
+
+			return Some(Cursor {
+				col: 1,
+				.. pos
+			});
+
+		}
+
+		return None;
+
+	}
+
+	fn prev_word(&self) -> Option<Cursor> {
+		return self.prev_word_at(self.cursor);
+	}
+
+	fn line_start_at(&self, mut pos: Cursor) -> Cursor {
+
+		if let Some(line) = self.get_line_at(pos.line) {
+
+			let mut index = 0;
+
+			let len = graphemes(&line).count();
+
+			for (i, g) in graphemes(&line).enumerate() {
+				if g != "\t" && g != " " {
+					index = i;
+					break;
+				} else if i == len - 1 {
+					index = i + 1;
+				}
+			}
+
+			pos.col = index as Col + 1;
+
+			return self.clamp_cursor(pos);
+
+		}
+
+		return pos;
+
+	}
+
+	fn move_line_start(&mut self) {
+		self.cursor = self.line_start_at(self.cursor);
+	}
+
+	fn line_end_at(&self, mut pos: Cursor) -> Cursor {
+
+		if let Some(len) = self.line_len_at(pos.line) {
+			pos.col = len + 1;
+			return self.clamp_cursor(pos);
+		}
+
+		return pos;
+
+	}
+
+	fn move_line_end(&mut self) {
+		self.cursor = self.line_end_at(self.cursor);
+	}
+
+	fn save(&mut self) -> Result<()> {
+
+		if self.scratch {
+			return Err(format!("not a file, write it somewhere with :w <path>"));
+		}
+
+		if self.conflict.is_some() {
+			return Err(format!("file changed on disk"));
+		}
+
+		// in case it changed since the last check
+		if self.disk.changed(&self.path) {
+			self.conflict = Some(Conflict::Changed);
+			return Err(format!("file changed on disk"));
+		}
+
+		let bytes = self.to_bytes()?;
+
+		if self.backup {
+			self.backup()?;
+		}
+
+		write_atomic(&self.path, &bytes)?;
+		self.undo.mark_saved();
+		self.remove_swap();
+		self.disk = DiskState::new(&self.path, &bytes);
+		self.disk_format = self.conf.format_label();
+		self.lsp_save();
+		self.git_reload();
+
+		return Ok(());
+
+	}
+
+	/// Write to another file, which the buffer is then of, only if the write went through.
+	fn save_as(&mut self, path: PathBuf) -> Result<()> {
+
+		if path == self.path {
+			return self.save();
+		}
+
+		let bytes = self.to_bytes()?;
+
+		write_atomic(&path, &bytes)?;
+
+		// the old file's left as it is on disk
+		self.remove_swap();
+		self.lsp_close();
+		self.unpublish_words();
+
+		self.path = path;
+		self.scratch = false;
+		self.conflict = None;
+		self.undo.mark_saved();
+		self.disk = DiskState::new(&self.path, &bytes);
+		self.disk_format = self.conf.format_label();
+
+		self.lsp_open();
+		self.publish_words();
+		self.git_reload();
+
+		return Ok(());
+
+	}
+
+	// content as it goes on disk, with the configured final newline, line endings and charset
+	fn to_bytes(&mut self) -> Result<Vec<u8>> {
+
+		if self.conf.trim_trailing_whitespace {
+			self.trim_all();
+		}
+
+		self.commit();
+
+		let eol = self.conf.end_of_line.unwrap_or(LineEnding::Lf);
+		let mut content = self.content();
+
+		// an empty buffer stays an empty file, unless the file was a single empty line
+		let empty_file = content.is_empty() && self.disk.hash == hash(&[]);
+
+		if self.conf.insert_final_newline.unwrap_or(false) && !empty_file {
+			content.push('\n');
+		}
+
+		if eol != LineEnding::Lf {
+			content = content.replace('\n', eol.as_str());
+		}
+
+		return encode(&content, self.conf.charset.unwrap_or(Charset::Utf8));
+
+	}
+
+	// one level of indentation for each level, as commands so they get recorded and repeated at every cursor
+	fn insert_indent(&mut self, level: usize) {
+		for _ in 0..level {
+			for ch in self.conf.indent_unit().chars() {
+				self.exec(Command::Insert(ch));
+			}
+		}
+	}
+
+	fn exec(&mut self, cmd: Command) {
+
+		if self.recording.is_some() {
+			self.record.push(cmd.clone());
+		}
+
+		if !self.cursors.is_empty() && cmd.multi() {
+			self.exec_multi(cmd);
+		} else {
+			self.exec_one(cmd);
+		}
+
+	}
+
+	fn exec_one(&mut self, cmd: Command) {
+
+		match cmd {
+			Command::Insert(ch) => self.insert(ch),
+			Command::MoveTo(c) => self.move_to(c),
+			Command::MoveUp => self.move_up(),
+			Command::MoveDown => self.move_down(),
+			Command::MoveLeft => self.move_left(),
+			Command::MoveRight => self.move_right(),
+			Command::MovePrevWord => self.move_prev_word(),
+			Command::MoveNextWord => self.move_next_word(),
+			Command::MoveLineStart => self.move_line_start(),
+			Command::MoveLineEnd => self.move_line_end(),
+			Command::DelLine => self.del_line(),
+			Command::DelWord => self.del_word(),
+			Command::Del => self.del(),
+			Command::Undo => {
+				self.clear_cursors();
+				self.undo();
+			},
+			Command::Redo => {
+				self.clear_cursors();
+				self.redo();
+			},
+			Command::BreakLine => self.break_line(),
+			Command::BreakLineIndent => self.break_line_indent(),
+			Command::DelPair => self.del_pair(),
+		}
+
+	}
+
+	fn search_backward(&self) -> Option<Cursor> {
+
+		let pat = match &self.search_pattern {
+			Some(pat) => pat,
+			None => return None,
+		};
+
+		for i in (0..self.cursor.line.max(0) as usize).rev() {
+
+			let l = match self.text.line(i) {
+				Some(l) => l,
+				None => continue,
+			};
+
+			for f in pat.find_iter(&l) {
+				let col = byte_to_col(&l, f.start()) as Col + 1;
+				if !(i as i32 + 1 == self.cursor.line && col >= self.cursor.col) {
+					return Some(Cursor::new(i as i32 + 1, col));
+				}
+			}
+		}
+
+		return None;
+
+	}
+
+	fn search_forward(&self) -> Option<Cursor> {
+
+		let pat = match &self.search_pattern {
+			Some(pat) => pat,
+			None => return None,
+		};
+
+		for i in (self.cursor.line - 1).max(0) as usize..self.text.len_lines() {
+
+			let l = match self.text.line(i) {
+				Some(l) => l,
+				None => continue,
+			};
+
+			for f in pat.find_iter(&l) {
+				let col = byte_to_col(&l, f.start()) as Col + 1;
+				if !(i as i32 + 1 == self.cursor.line && col <= self.cursor.col) {
+					return Some(Cursor::new(i as i32 + 1, col));
+				}
+			}
+		}
+
+		return None;
+
+	}
+
+	fn trim_all(&mut self) {
+
+		for i in (0..self.text.len_lines()).rev() {
+
+			if let Some(l) = self.text.line(i) {
+
+				let len = l.chars().count();
+				let trimmed_len = l.trim_end().chars().count();
+
+				if trimmed_len < len {
+					let start = self.text.pos_to_char(i, 0);
+					self.text.remove(start + trimmed_len, start + len);
+				}
+
+			}
+
+		}
+
+		self.move_to(self.cursor);
+
+	}
+
+}
+
+impl Buffer for TextEditor {
+
+	fn title(&self) -> String {
+		return self.path
+			.file_name()
+			.map(|s| s.to_string_lossy().to_string())
+			.unwrap_or(String::new());
+	}
+
+	fn path(&self) -> Option<&Path> {
+		if self.scratch {
+			return None;
+		}
+		return Some(&self.path);
+	}
+
+	fn modified(&self) -> bool {
+		return self.is_modified();
+	}
+
+	fn set_view_size(&mut self, w: f32, h: f32) {
+		self.view_size = Some((w, h));
+	}
+
+	fn busy(&self) -> bool {
+		return self.mode == Mode::Insert || self.mode == Mode::Select;
+	}
+
+	fn closable(&self) -> bool {
+		return self.scratch || !self.is_modified();
+	}
+
+	fn log(&mut self) -> Option<&mut Vec<Msg>> {
+		return Some(&mut self.log);
+	}
+
+	fn actions(&mut self) -> Option<&mut Vec<Action>> {
+		return Some(&mut self.actions);
+	}
+
+	fn handle_lsp(&mut self) {
+		self.update_lsp();
+	}
+
+	fn close(&mut self) {
+		self.commit();
+		self.close_swap();
+		self.lsp_close();
+		self.unpublish_words();
+		if self.scratch {
+			return;
+		}
+		if let Err(e) = self.undo.save(&self.path, self.disk.hash) {
+			elog!("{}", e);
+		}
+	}
+
+	fn event(&mut self, d: &mut Ctx, e: &input::Event) -> Result<()> {
+
+		let kmods = d.window.key_mods();
+
+		if self.conflict.is_some() {
+			if let Event::CharInput(ch) = e {
+				self.resolve_conflict(*ch);
+			}
+			return Ok(());
+		}
+
+		if let Event::KeyPress(_) = e {
+			self.ide.hover = None;
+		}
+
+		if self.completion.is_some() {
+			match e {
+				Event::KeyPress(Key::Esc) => {
+					self.completion = None;
+					return Ok(());
+				},
+				Event::KeyPressRepeat(Key::Up) => {
+					self.select_completion(false);
+					return Ok(());
+				},
+				Event::KeyPressRepeat(Key::Down) => {
+					self.select_completion(true);
+					return Ok(());
+				},
+				// a snippet's trigger word expands instead
+				Event::KeyPressRepeat(Key::Tab) if self.expand_snippet() => {
+					self.completion = None;
+					return Ok(());
+				},
+				Event::KeyPressRepeat(Key::Enter) | Event::KeyPressRepeat(Key::Tab) => {
+					self.accept_completion();
+					return Ok(());
+				},
+				_ => {},
+			}
+		}
+
+		if self.confirm.is_some() {
+			match e {
+				Event::KeyPress(Key::Esc) => self.confirm = None,
+				Event::CharInput(ch) => {
+					match ch {
+						'y' => self.confirm_match(true),
+						'n' => self.confirm_match(false),
+						'a' => self.confirm_all(),
+						'q' => self.confirm = None,
+						_ => {},
+					}
+				},
+				_ => {},
+			}
+			if self.confirm.is_none() {
+				self.commit();
+			}
+			return Ok(());
+		}
+
+		if self.pending.is_some() {
+			match e {
+				Event::KeyPress(Key::Esc) => {
+					self.pending = None;
+					return Ok(());
+				},
+				Event::KeyPress(_) | Event::KeyPressRepeat(_) => return Ok(()),
+				Event::CharInput(ch) => {
+					match self.pending.take() {
+						Some(Pending::Replace) => self.replace_sel(*ch),
+						Some(Pending::Register) => self.reg = Some(*ch),
+						Some(Pending::Record) => self.start_record(*ch),
+						Some(Pending::Replay) => {
+							let n = self.count.take().unwrap_or(1);
+							self.replay(*ch, n);
+						},
+						None => {},
+					}
+					return Ok(());
+				},
+				_ => {},
+			}
+		}
+
+		match e {
+
+			Event::KeyPress(k) => {
+
+				match self.mode {
+					Mode::Normal => {
+						match k {
+							Key::Enter if kmods.alt => {
+								self.insert_line();
+								self.highlight();
+							}
+							Key::Enter => self.mode = Mode::Insert,
+							Key::W => self.save()?,
+							Key::Esc => {
+								self.count = None;
+								self.clear_cursors();
+							},
+							Key::Period if kmods.alt => {
+								if let Some(slot) = self.last_macro {
+									let n = self.count.take().unwrap_or(1);
+									self.replay(slot, n);
+								}
+							},
+							Key::V if kmods.alt => self.start_select(SelectKind::Line),
+							Key::V => self.start_select(SelectKind::Char),
+							Key::B => self.start_select(SelectKind::Block),
+							Key::Z => self.conf.soft_wrap = !self.conf.soft_wrap,
+							Key::G if kmods.alt => self.find_references(),
+							Key::G => self.goto_definition(),
+							Key::C => self.next_hunk(!kmods.alt),
+							Key::I => self.request_hover(),
+							_ => {},
+						}
+					},
+					Mode::Insert => {
+						match k {
+							Key::Esc => self.mode = Mode::Normal,
+							_ => {},
+						}
+					},
+					Mode::Select => {
+						match k {
+							Key::Esc => self.stop_select(),
+							_ => {},
+						}
+					},
+					Mode::Command => {
+						match k {
+							Key::Esc => {
+								self.cmd_range = None;
+								self.mode = Mode::Normal;
+							},
+							Key::Enter => {
+								self.mode = Mode::Normal;
+								self.run_cmd();
+							},
+							_ => {},
+						}
+					},
+
+				}
+
+			}
+
+			Event::KeyPressRepeat(k) => {
+
+				match self.mode {
+
+					Mode::Normal => {
+
+						match *k {
+							Key::K if kmods.alt => self.add_cursor_vertical(false),
+							Key::J if kmods.alt => self.add_cursor_vertical(true),
+							Key::K => self.exec(Command::MoveUp),
+							Key::J => self.exec(Command::MoveDown),
+							Key::H => {
+								if kmods.alt {
+									self.exec(Command::MovePrevWord);
+								} else {
+									self.exec(Command::MoveLeft);
+								}
+							},
+							Key::L => {
+								if kmods.alt {
+									self.exec(Command::MoveNextWord);
+								} else {
+									self.exec(Command::MoveRight);
+								}
+							},
+							Key::Left => self.exec(Command::MoveLeft),
+							Key::Right => self.exec(Command::MoveRight),
+							Key::Up => self.exec(Command::MoveUp),
+							Key::Down => self.exec(Command::MoveDown),
+							Key::D => {
+								self.exec(Command::DelLine);
+								self.highlight();
+							},
+							Key::U => {
+								if kmods.alt {
+									self.travel(TIME_STEP, false);
+								} else {
+									self.exec(Command::Undo);
+								}
+								self.highlight();
+							},
+							Key::O => {
+								if kmods.alt {
+									self.travel(TIME_STEP, true);
+								} else {
+									self.exec(Command::Redo);
+								}
+								self.highlight();
+							},
+							Key::Y => {
+								if kmods.alt {
+									self.cycle_put();
+								} else {
+									self.yank_line();
+								}
+							},
+							Key::P => self.put(!kmods.alt),
+							Key::Semicolon if kmods.alt => {
+								if let Some(pos) = self.search_backward() {
+									self.exec(Command::MoveTo(pos));
+								}
+							},
+							Key::Quote if kmods.alt => {
+								if let Some(pos) = self.search_forward() {
+									self.exec(Command::MoveTo(pos));
+								}
+							},
+							_ => {},
+						}
+
+					},
+
+					Mode::Insert => {
+
+						match k {
+
+							Key::Backspace => {
+
+								if kmods.alt {
+									self.exec(Command::DelWord);
+									self.highlight();
+								} else {
+									self.exec(Command::DelPair);
+									self.highlight();
+								}
+
+							},
+
+							Key::Enter => {
+								self.exec(Command::BreakLineIndent);
+								self.highlight();
+							},
+
+							Key::Left => self.exec(Command::MoveLeft),
+							Key::Right => self.exec(Command::MoveRight),
+							Key::Tab if kmods.alt => self.complete(true),
+							Key::Tab => {
+								if !self.expand_snippet() && !self.next_tabstop(kmods.shift) {
+									self.insert_indent(1);
+									self.highlight();
+								}
+							},
+							_ => {},
+						}
+
+					},
+
+					Mode::Select => {
+
+						match *k {
+							Key::K => self.exec(Command::MoveUp),
+							Key::J => self.exec(Command::MoveDown),
+							Key::H => {
+								if kmods.alt {
+									self.exec(Command::MovePrevWord);
+								} else {
+									self.exec(Command::MoveLeft);
+								}
+							},
+							Key::L => {
+								if kmods.alt {
+									self.exec(Command::MoveNextWord);
+								} else {
+									self.exec(Command::MoveRight);
+								}
+							},
+							Key::Left => self.exec(Command::MoveLeft),
+							Key::Right => self.exec(Command::MoveRight),
+							Key::Up => self.exec(Command::MoveUp),
+							Key::Down => self.exec(Command::MoveDown),
+							Key::D => self.del_sel(),
+							Key::Y => self.yank_sel(),
+							Key::Tab => {
+								if kmods.alt {
+									self.outdent_sel();
+								} else {
+									self.indent_sel();
+								}
+							},
+							_ => {},
+						}
+
+					},
+
+					Mode::Command => {
+						match k {
+							Key::Backspace if kmods.alt => self.cmd_bar.del_word(),
+							Key::Backspace => self.cmd_bar.del(),
+							Key::Left => self.cmd_bar.move_left(),
+							Key::Right => self.cmd_bar.move_right(),
+							_ => {},
+						}
+					},
+
+				}
+
+			},
+
+			Event::CharInput(ch) => {
+
+				match self.mode {
+
+					Mode::Normal => {
+
+						match ch {
+							'<' => {
+								self.exec(Command::MoveLineStart);
+								self.mode = Mode::Insert;
+							},
+							'>' => {
+								self.exec(Command::MoveLineEnd);
+								self.mode = Mode::Insert;
+							},
+							'?' => self.open_cmd_bar(""),
+							':' => self.open_cmd_bar(":"),
+							'/' => self.toggle_comment(),
+							'"' => self.pending = Some(Pending::Register),
+							'\\' => {
+								if self.recording.is_some() {
+									self.stop_record();
+								} else {
+									self.pending = Some(Pending::Record);
+								}
+							},
+							'@' => self.pending = Some(Pending::Replay),
+							'*' => self.add_cursor_at_match(false),
+							'&' => self.add_cursor_at_match(true),
+							'%' => self.jump_bracket(),
+							'!' => self.next_diag(),
+							'-' => self.toggle_fold(),
+							'_' => {
+								let level = self.count.take().unwrap_or(0);
+								self.fold_level(level);
+							},
+							'+' => self.unfold_all(),
+							'0'..='9' => {
+								let d = *ch as usize - '0' as usize;
+								self.count = Some(self.count.unwrap_or(0).saturating_mul(10).saturating_add(d));
+							},
+							'[' => {
+								self.switch_branch(false);
+								self.highlight();
+							},
+							']' => {
+								self.switch_branch(true);
+								self.highlight();
+							},
+							_ => {},
+						}
+
+					},
+
+					Mode::Insert => {
+
+						self.snippet_typing();
+						self.exec(Command::Insert(*ch));
+
+						if let Some(wch) = WRAP_CHARS.get(ch) {
+							self.exec(Command::Insert(*wch));
+							self.exec(Command::MoveLeft);
+						}
+
+						self.highlight();
+
+						if self.is_trigger_char(*ch) {
+							self.request_completion();
+						} else if ch.is_alphanumeric() || *ch == '_' || *ch == '/' {
+							self.typed = Some(Instant::now());
+						}
+
+					},
+
+					Mode::Select => {
+
+						match ch {
+							'<' => self.exec(Command::MoveLineStart),
+							'>' => self.exec(Command::MoveLineEnd),
+							'/' => self.comment_sel(),
+							'*' => self.block_comment_sel(),
+							'r' => self.pending = Some(Pending::Replace),
+							'%' => self.jump_bracket(),
+							'c' => self.change_sel(),
+							'"' => self.pending = Some(Pending::Register),
+							'?' => self.open_cmd_bar(""),
+							':' => self.open_cmd_bar(":"),
+							_ => {},
+						}
+
+					},
+
+					Mode::Command => {
+						self.cmd_bar.insert(*ch);
+					},
+
+				}
+
+			}
+
+			Event::Wheel(d, _) => {
+
+				if let Mode::Normal = self.mode {
+
+					let y = d.y * 0.1;
+					self.scroll_remainder = (y + self.scroll_remainder) % 1.0;
+					let y = (y + self.scroll_remainder) as i32;
+
+					for _ in 0..y.abs() {
+						if y > 0 {
+							self.move_down();
+						} else if y < 0 {
+							self.move_up();
+						}
+					}
+
+				}
+
+			},
+
+			_ => {},
+
+		}
+
+		// insert mode edits are undone together, so is a whole substitution
+		if self.mode != Mode::Insert && self.confirm.is_none() {
+			self.commit();
+		}
+
+		return Ok(());
+
+	}
+
+	fn update(&mut self, d: &mut Ctx) -> Result<()> {
+
+		let (vw, vh) = self.view_size.unwrap_or((d.gfx.width() as f32, d.gfx.height() as f32));
+
+		// monospace, so any char gives the cell width
+		let cell = shapes::text("0000000000")
+			.size(FONT_SIZE)
+			.format(d.gfx)
+			.cursor_pos(10)
+			.map(|p| p.x / 10.0)
+			.unwrap_or(CURSOR_WIDTH);
+
+		self.update_wrap(((vw - CURSOR_WIDTH - self.gutter_width(cell)) / cell).max(1.0) as usize, cell);
+		self.check_disk();
+		self.update_swap();
+		self.highlight();
+		self.update_lsp();
+		self.update_completion();
+		self.update_completion_sources();
+		self.sync_snippet();
+		self.update_git();
+		self.reveal_cursor();
+
+		let th = vh - FONT_SIZE - LINE_SPACING * 2.0;
+		let height = LINE_HEIGHT * (self.display_row(self.cursor) + 1) as f32;
+
+		let y = height - self.scroll_off;
+
+		if y > th {
+			self.scroll_off = height - th;
+		}
+
+		if self.scroll_off > (height - LINE_HEIGHT) {
+			self.scroll_off = height - LINE_HEIGHT;
+		}
+
+		return Ok(());
+
+	}
+
+	fn draw(&self, gfx: &mut Gfx) -> Result<()> {
+
+		let mut y = LINE_SPACING * 0.5;
+		let (vw, vh) = self.view_size.unwrap_or((gfx.width() as f32, gfx.height() as f32));
+		let th = vh - FONT_SIZE - LINE_SPACING * 2.0;
+
+		// TODO: apply scroll off remainder
+		let r1 = f32::floor(self.scroll_off / LINE_HEIGHT) as usize;
+		let r2 = f32::ceil((self.scroll_off + th) / LINE_HEIGHT) as usize;
+		let (mut i, mut first_row) = self.row_to_line(r1);
+		let mut row = r1;
+
+		let cursor = self.cursor;
+		let bracket = self.match_bracket();
+		// text starts after the line numbers
+		let gutter = self.gutter_width(self.wrap.cell);
+		// where popups go
+		let mut cursor_at = None;
+
+		while row < r2 && y < th {
+
+			let chunks = match self.rendered_lines.get(i) {
+				Some(chunks) => chunks,
+				None => break,
+			};
+
+			let line = self.get_line_at(i as Line + 1).unwrap_or(String::new());
+			let len = graphemes(&line).count() as Col;
+			let len_chars = line.chars().count();
+			let rows = self.line_rows(i);
+			let char_at = |c: Col| col_to_char(&line, (c - 1).max(0) as usize);
+
+			for r in first_row..rows.len() {
+
+				// chars s..e are on this row, the last row also has the line end
+				let s = rows[r];
+				let last = r + 1 == rows.len();
+				let e = if last { len_chars } else { rows[r + 1] };
+				let in_row = |ci: usize| ci >= s && (ci < e || last);
+				let indent_x = gutter + if r > 0 { self.wrap_indent_x(&line) } else { 0.0 };
+
+				let row_chunks = slice_chunks(chunks, s, if last { usize::MAX } else { e });
+
+				let chunks = row_chunks
+					.iter()
+					.map(|c| {
+						return shapes::TextChunk::colored(&c.text, c.color);
+					})
+					.collect::<Vec<shapes::TextChunk>>();
+
+				let ftext = shapes::Text::from_chunks(&chunks)
+					.align(gfx::Origin::TopLeft)
+					.line_spacing(LINE_SPACING)
+					.size(FONT_SIZE)
+					.tab_width(self.conf.tab_width)
+					.format(gfx)
+					;
+
+				let xc = |ci: usize| {
+					return ftext
+						.cursor_pos(ci.max(s).min(e) - s)
+						.map(|p| p.x)
+						.unwrap_or(0.0) + indent_x;
+				};
+
+				// draw selection
+				if let Some((c1, c2)) = self.sel_cols_at(i as Line + 1) {
+
+					let (ci1, ci2) = (char_at(c1), char_at(c2));
+					let padding = 2.0;
+
+					if (ci2 > s || r == 0) && (ci1 < e || last) {
+
+						let x1 = if ci1 <= s && r > 0 { gutter } else { xc(ci1) };
+
+						let x2 = if self.selection.kind == SelectKind::Line {
+							vw
+						} else if c2 > len + 1 && last {
+							xc(len_chars) + CURSOR_WIDTH
+						} else if ci2 >= e && !last {
+							vw
+						} else {
+							xc(ci2)
+						};
+
+						gfx.draw(
+							&shapes::rect(
+								vec2!(x1, -y + padding),
+								vec2!(x2, -y - FONT_SIZE - padding)
+							)
+								.fill(rgba!(1, 0.5, 0.5, 0.3))
+								,
+						)?;
+
+					}
+
+				}
+
+				// draw match waiting for confirmation
+				if let Some((c1, c2)) = self.confirm_cols_at(i as Line + 1) {
+
+					let (ci1, ci2) = (char_at(c1), char_at(c2));
+					let padding = 2.0;
+
+					if in_row(ci1) || (ci1 < s && ci2 > s) {
+						gfx.draw(
+							&shapes::rect(
+								vec2!(xc(ci1), -y + padding),
+								vec2!(xc(ci2).max(xc(ci1) + CURSOR_WIDTH), -y - FONT_SIZE - padding)
+							)
+								.fill(rgba!(1, 1, 0.5, 0.4))
+								,
+						)?;
+					}
+
+				}
+
+				// draw the tabstop being filled in
+				for (c1, c2) in self.tabstop_cols_at(i as Line + 1) {
+
+					let (ci1, ci2) = (char_at(c1), char_at(c2));
+					let padding = 2.0;
+
+					if in_row(ci1) {
+						gfx.draw(
+							&shapes::rect(
+								vec2!(xc(ci1), -y + padding),
+								vec2!(xc(ci2).max(xc(ci1) + CURSOR_WIDTH), -y - FONT_SIZE - padding)
+							)
+								.fill(rgba!(0.5, 0.8, 1, 0.3))
+								,
+						)?;
+					}
+
+				}
+
+				// draw matching brackets, or the bracket in red if it's unbalanced
+				if let Some((pos, m)) = bracket {
+
+					let color = if m.is_some() { rgba!(1, 1, 1, 0.25) } else { rgba!(1, 0.3, 0.3, 0.6) };
+					let padding = 2.0;
+
+					for p in [Some(pos), m].iter().flatten().filter(|p| p.line == i as Line + 1) {
+						let ci = char_at(p.col);
+						if in_row(ci) && ci < e {
+							gfx.draw(
+								&shapes::rect(
+									vec2!(xc(ci), -y + padding),
+									vec2!(xc(ci + 1).max(xc(ci) + CURSOR_WIDTH), -y - FONT_SIZE - padding)
+								)
+									.fill(color)
+									,
+							)?;
+						}
+					}
+
+				}
+
+				// draw other cursors
+				for c in self.cursors.iter().filter(|c| c.line == i as Line + 1) {
+
+					let ci = char_at(c.col);
+					let padding = 2.0;
+
+					if in_row(ci) {
+						let x = xc(ci);
+						gfx.draw(
+							&shapes::rect(
+								vec2!(x, -y + padding),
+								vec2!(x + CURSOR_WIDTH, -y - FONT_SIZE - padding)
+							)
+								.fill(rgba!(1, 1, 1, 0.5))
+								,
+						)?;
+					}
+
+				}
+
+				if cursor.line == i as i32 + 1 && in_row(char_at(cursor.col)) {
+
+					let color = match self.mode {
+						Mode::Normal => rgba!(1),
+						Mode::Insert => rgba!(0, 1, 1, 1),
+						Mode::Select => rgba!(1),
+						Mode::Command => rgba!(1, 1, 1, 0),
+					};
+
+					let x = xc(char_at(cursor.col));
+					let padding = 2.0;
+
+					// wide chars take up 2 cells
+					let cells = self.cur_grapheme()
+						.map(|g| width(&g))
+						.unwrap_or(1)
+						.max(1);
+
+					cursor_at = Some((x, y));
+
+					// draw cursor
+					gfx.draw(
+						&shapes::rect(
+							vec2!(x, -y + padding),
+							vec2!(x + CURSOR_WIDTH * cells as f32, -y - FONT_SIZE - padding)
+						)
+							.fill(color)
+							,
+					)?;
+
+					// draw cursor line
+					gfx.draw(
+						&shapes::rect(
+							vec2!(0, -y + padding),
+							vec2!(vw, -y - FONT_SIZE - padding)
+						)
+							.fill(rgba!(1, 1, 1, 0.1))
+							,
+					)?;
+
+				}
+
+				// draw diagnostic underlines
+				for d in self.ide.diags.iter().filter(|d| d.start.line <= i as Line + 1 && d.end.line >= i as Line + 1) {
+
+					let ci1 = if d.start.line == i as Line + 1 { char_at(d.start.col) } else { 0 };
+					let ci2 = if d.end.line == i as Line + 1 { char_at(d.end.col) } else { len_chars };
+
+					if ci1 < e.max(s + 1) && (ci2 > s || r == 0) {
+						let x1 = xc(ci1.max(s));
+						let x2 = xc(ci2.min(e)).max(x1 + CURSOR_WIDTH);
+						gfx.draw(
+							&shapes::rect(
+								vec2!(x1, -y - FONT_SIZE - 1.0),
+								vec2!(x2, -y - FONT_SIZE - 2.5)
+							)
+								.fill(severity_color(d.severity))
+								,
+						)?;
+					}
+
+				}
+
+				gfx.draw_t(
+					mat4!()
+						.t2(vec2!(indent_x, -y))
+						,
+					&ftext,
+				)?;
+
+				if r == 0 && gutter > 0.0 {
+					self.draw_gutter(gfx, i, y)?;
+				}
+
+				// draw placeholder of a closed fold, or the diagnostic on the line
+				if let (true, Some((_, fe))) = (last, self.fold_at(i)) {
+					gfx.draw_t(
+						mat4!()
+							.t2(vec2!(xc(len_chars) + CURSOR_WIDTH, -y))
+							,
+						&shapes::text(&format!("··· {} lines", fe - i))
+							.align(gfx::Origin::TopLeft)
+							.size(FONT_SIZE)
+							.color(rgba!(1, 1, 1, 0.4))
+					)?;
+				} else if let (true, Some(d)) = (last, self.line_diag(i as Line + 1)) {
+					let mut color = severity_color(d.severity);
+					color.a = 0.6;
+					gfx.draw_t(
+						mat4!()
+							.t2(vec2!(xc(len_chars) + CURSOR_WIDTH * 2.0, -y))
+							,
+						&shapes::text(d.message.lines().next().unwrap_or(""))
+							.align(gfx::Origin::TopLeft)
+							.size(FONT_SIZE)
+							.color(color)
+					)?;
+				}
+
+				y += LINE_HEIGHT;
+				row += 1;
+
+				if row >= r2 || y >= th {
+					break;
+				}
+
+			}
+
+			first_row = 0;
+			i = match self.next_visible(i, true) {
+				Some(i) => i,
+				None => break,
+			};
+
+		}
+
+		if let Some((x, y)) = cursor_at {
+			self.draw_completion(gfx, x, y + LINE_HEIGHT)?;
+			self.draw_hover(gfx, x, y + LINE_HEIGHT)?;
+		}
+
+		let (m, c) = match self.mode {
+			_ if self.conflict == Some(Conflict::Changed) => ("changed on disk: reload, keep, diff? (r/k/d)", rgba!(1, 0.5, 0.5, 1)),
+			_ if self.conflict == Some(Conflict::Deleted) => ("deleted on disk: keep, close? (k/c)", rgba!(1, 0.5, 0.5, 1)),
+			_ if self.conflict == Some(Conflict::Swap) => ("unsaved changes in swap file: recover, discard? (r/d)", rgba!(1, 0.5, 0.5, 1)),
+			Mode::Normal if self.confirm.is_some() => ("replace? (y/n/a/q)", rgba!(1, 1, 0.5, 1)),
+			Mode::Normal => ("normal", rgba!(0.5, 1, 1, 1)),
+			Mode::Insert => ("insert", rgba!(0.5, 1, 0.5, 1)),
+			Mode::Select => ("select", rgba!(1, 0.5, 0.5, 1)),
+			Mode::Command => ("command", rgba!(1, 1, 0.5, 1)),
+		};
+
+		gfx.draw(
+			&shapes::rect(
+				vec2!(0, -vh + FONT_SIZE + LINE_SPACING * 2.0),
+				vec2!(vw, -vh),
+			)
+				.fill(c)
+		)?;
+
+		gfx.draw_t(
+			mat4!()
+				.t2(vec2!(LINE_SPACING, -vh + LINE_SPACING))
+				,
+			&shapes::text(&format!("{}", m.to_uppercase()))
+				.align(Origin::BottomLeft)
+				.size(FONT_SIZE)
+				.color(rgba!(0, 0, 0, 1))
+		)?;
+
+		gfx.draw_t(
+			mat4!()
+				.t2(vec2!(vw - LINE_SPACING, -vh + LINE_SPACING))
+				,
+			&shapes::text(&format!(
+				"{}{}{}{} {}",
+				self.recording.map(|s| format!("recording @{} ", s)).unwrap_or(String::new()),
+				self.count.map(|n| format!("{} ", n)).unwrap_or(String::new()),
+				self.reg.map(|r| format!("\"{} ", r)).unwrap_or(String::new()),
+				self.conf.format_label(),
+				self.cursor,
+			))
+				.align(Origin::BottomRight)
+				.size(FONT_SIZE)
+				.color(rgba!(0, 0, 0, 1))
+		)?;
+
+		if let Mode::Command = self.mode {
+
+			gfx.draw(
+				&shapes::rect(
+					vec2!(0, -vh + FONT_SIZE + 4.0 + FONT_SIZE),
+					vec2!(vw, -vh + FONT_SIZE + 4.0),
+				)
+					.fill(rgba!(0, 0, 0, 1))
+			)?;
+
+			let cmd_bar = shapes::text(self.cmd_bar.content())
+				.align(Origin::BottomLeft)
+				.size(FONT_SIZE)
+				.format(gfx);
+
+			if let Some(pos) = cmd_bar.cursor_pos(self.cmd_bar.cursor() as usize) {
+				gfx.draw_t(
+					mat4!()
+						.t2(vec2!(0, -vh + FONT_SIZE + LINE_SPACING * 2.0))
+						.t2(pos)
+						,
+					&shapes::rect(vec2!(0), vec2!(12.0, FONT_SIZE)),
+				)?;
+			}
+
+			gfx.draw_t(
+				mat4!()
+					.t2(vec2!(0, -vh + FONT_SIZE + LINE_SPACING * 2.0))
+					,
+				&cmd_bar
+			)?;
+
+		}
+
+		return Ok(());
+
+	}
+
+}
+
@@ -0,0 +1,218 @@
+// wengwengweng
+
+use std::io::Write;
+use std::process::Command;
+use std::process::Stdio;
+use std::collections::HashMap;
+use std::collections::VecDeque;
+
+use super::select::SelectKind;
+
+const HISTORY_SIZE: usize = 10;
+
+// (copy command, paste command)
+const CLIPBOARDS: &[(&[&str], &[&str])] = &[
+	(&["pbcopy"], &["pbpaste"]),
+	(&["wl-copy"], &["wl-paste", "-n"]),
+	(&["xclip", "-selection", "clipboard"], &["xclip", "-selection", "clipboard", "-o"]),
+];
+
+#[derive(Clone, Debug, PartialEq)]
+pub struct Yank {
+	pub text: String,
+	pub kind: SelectKind,
+}
+
+impl Yank {
+	fn from_text(text: String) -> Self {
+		let kind = if text.ends_with('\n') {
+			SelectKind::Line
+		} else {
+			SelectKind::Char
+		};
+		return Self {
+			text: text,
+			kind: kind,
+		};
+	}
+}
+
+/// Yanked text shared by all text editors.
+///
+/// `a` - `z` are named registers (uppercase appends), `0` - `9` index the yank history
+/// where `0` is the unnamed register, and `+` is the system clipboard.
+pub struct Registers {
+	named: HashMap<char, Yank>,
+	history: VecDeque<Yank>,
+	clipboard: Option<Clipboard>,
+	last_clip: Option<String>,
+}
+
+impl Registers {
+
+	pub fn new(clipboard: bool) -> Self {
+		return Self {
+			named: HashMap::new(),
+			history: VecDeque::with_capacity(HISTORY_SIZE),
+			clipboard: if clipboard { Clipboard::detect() } else { None },
+			last_clip: None,
+		};
+	}
+
+	pub fn set(&mut self, reg: Option<char>, y: Yank) {
+
+		match reg {
+			Some('+') => {
+				if let Some(clip) = &self.clipboard {
+					clip.set(&y.text);
+				}
+				return;
+			},
+			Some(c) if c.is_ascii_uppercase() => {
+				let c = c.to_ascii_lowercase();
+				if let Some(prev) = self.named.get_mut(&c) {
+					prev.text.push_str(&y.text);
+				} else {
+					self.named.insert(c, y.clone());
+				}
+			},
+			Some(c) if c.is_ascii_lowercase() => {
+				self.named.insert(c, y.clone());
+			},
+			_ => {},
+		}
+
+		if let Some(clip) = &self.clipboard {
+			clip.set(&y.text);
+			self.last_clip = Some(y.text.clone());
+		}
+
+		self.push_history(y);
+
+	}
+
+	pub fn get(&mut self, reg: Option<char>) -> Option<Yank> {
+
+		return match reg {
+			None | Some('"') => {
+				self.sync_clipboard();
+				self.history.front().cloned()
+			},
+			Some('+') => {
+				self.clipboard
+					.as_ref()
+					.and_then(|c| c.get())
+					.map(Yank::from_text)
+			},
+			Some(c) if c.is_ascii_digit() => {
+				self.history.get(c as usize - '0' as usize).cloned()
+			},
+			Some(c) => self.named.get(&c.to_ascii_lowercase()).cloned(),
+		};
+
+	}
+
+	/// Entry in yank history, 0 is the latest.
+	pub fn history(&self, n: usize) -> Option<&Yank> {
+		return self.history.get(n);
+	}
+
+	pub fn history_len(&self) -> usize {
+		return self.history.len();
+	}
+
+	fn push_history(&mut self, y: Yank) {
+
+		if self.history.front() == Some(&y) {
+			return;
+		}
+
+		self.history.push_front(y);
+		self.history.truncate(HISTORY_SIZE);
+
+	}
+
+	// pick up text copied from other programs
+	fn sync_clipboard(&mut self) {
+
+		let text = match self.clipboard.as_ref().and_then(|c| c.get()) {
+			Some(text) => text,
+			None => return,
+		};
+
+		if text.is_empty() || Some(&text) == self.last_clip.as_ref() {
+			return;
+		}
+
+		self.last_clip = Some(text.clone());
+		self.push_history(Yank::from_text(text));
+
+	}
+
+}
+
+struct Clipboard {
+	copy: &'static [&'static str],
+	paste: &'static [&'static str],
+}
+
+impl Clipboard {
+
+	fn detect() -> Option<Self> {
+
+		for (copy, paste) in CLIPBOARDS {
+
+			let available = Command::new(paste[0])
+				.args(&paste[1..])
+				.stdin(Stdio::null())
+				.stdout(Stdio::null())
+				.stderr(Stdio::null())
+				.status()
+				.is_ok();
+
+			if available {
+				return Some(Self {
+					copy: copy,
+					paste: paste,
+				});
+			}
+
+		}
+
+		return None;
+
+	}
+
+	fn get(&self) -> Option<String> {
+
+		let out = Command::new(self.paste[0])
+			.args(&self.paste[1..])
+			.stdin(Stdio::null())
+			.stderr(Stdio::null())
+			.output()
+			.ok()?;
+
+		return String::from_utf8(out.stdout).ok();
+
+	}
+
+	fn set(&self, text: &str) {
+
+		let child = Command::new(self.copy[0])
+			.args(&self.copy[1..])
+			.stdin(Stdio::piped())
+			.stdout(Stdio::null())
+			.stderr(Stdio::null())
+			.spawn();
+
+		if let Ok(mut child) = child {
+			if let Some(stdin) = child.stdin.as_mut() {
+				stdin.write_all(text.as_bytes()).ok();
+			}
+			drop(child.stdin.take());
+			child.wait().ok();
+		}
+
+	}
+
+}
@@ -0,0 +1,292 @@
+// wengwengweng
+
+use super::*;
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum SelectKind {
+	Char,
+	Line,
+	Block,
+}
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct Selection {
+	pub anchor: Cursor,
+	pub kind: SelectKind,
+}
+
+impl TextEditor {
+
+	pub(super) fn start_select(&mut self, kind: SelectKind) {
+		self.selection = Selection {
+			anchor: self.cursor,
+			kind: kind,
+		};
+		self.mode = Mode::Select;
+	}
+
+	pub(super) fn stop_select(&mut self) {
+		self.pending = None;
+		self.mode = Mode::Normal;
+	}
+
+	// ordered start / end of selection, both inclusive
+	pub(super) fn sel_bounds(&self) -> (Cursor, Cursor) {
+		let a = self.clamp_cursor(self.selection.anchor);
+		let b = self.cursor;
+		return (a.min(b), a.max(b));
+	}
+
+	/// Selected grapheme cols on a line as [start, end), end past the line length means the line break is included.
+	pub(super) fn sel_cols_at(&self, ln: Line) -> Option<(Col, Col)> {
+
+		if self.mode != Mode::Select {
+			return None;
+		}
+
+		let (start, end) = self.sel_bounds();
+
+		if ln < start.line || ln > end.line {
+			return None;
+		}
+
+		let len = self.line_len_at(ln)?;
+
+		return match self.selection.kind {
+			SelectKind::Char => {
+				let c1 = if ln == start.line { start.col } else { 1 };
+				let c2 = if ln == end.line { end.col + 1 } else { len + 2 };
+				Some((c1, c2))
+			},
+			SelectKind::Line => Some((1, len + 2)),
+			SelectKind::Block => {
+
+				let line = self.get_line_at(ln)?;
+				let (v1, v2) = self.block_vcols();
+				let mut c1 = None;
+				let mut c2 = len + 1;
+
+				for i in 0..len as usize {
+					let vc = display_col(&line, i, self.conf.tab_width);
+					if vc >= v1 && vc <= v2 {
+						if c1.is_none() {
+							c1 = Some(i as Col + 1);
+						}
+						c2 = i as Col + 2;
+					} else if vc > v2 {
+						break;
+					}
+				}
+
+				c1.map(|c1| (c1, c2))
+
+			},
+		};
+
+	}
+
+	// display column span of a block selection, inclusive
+	fn block_vcols(&self) -> (usize, usize) {
+
+		let vcol = |pos: Cursor| {
+			return self.get_line_at(pos.line)
+				.map(|l| display_col(&l, (pos.col - 1).max(0) as usize, self.conf.tab_width))
+				.unwrap_or(0);
+		};
+
+		let a = vcol(self.clamp_cursor(self.selection.anchor));
+		let b = vcol(self.cursor);
+
+		return (a.min(b), a.max(b));
+
+	}
+
+	// char index ranges covered by the selection, from top to bottom
+	fn sel_ranges(&self) -> Vec<(usize, usize)> {
+
+		let (start, end) = self.sel_bounds();
+		let mut ranges = vec![];
+
+		for ln in start.line..=end.line {
+
+			if let (Some((c1, c2)), Some(len)) = (self.sel_cols_at(ln), self.line_len_at(ln)) {
+
+				let s = self.pos_to_idx(Cursor::new(ln, c1));
+				let mut e = self.pos_to_idx(Cursor::new(ln, c2.min(len + 1)));
+
+				// include the line break
+				if c2 > len + 1 && (ln as usize) < self.text.len_lines() {
+					e += 1;
+				}
+
+				ranges.push((s, e));
+
+			}
+
+		}
+
+		if self.selection.kind != SelectKind::Block {
+			if let (Some(first), Some(last)) = (ranges.first(), ranges.last()) {
+				return vec![(first.0, last.1)];
+			}
+		}
+
+		return ranges;
+
+	}
+
+	pub(super) fn sel_text(&self) -> String {
+
+		let ranges = self.sel_ranges();
+
+		let mut text = ranges
+			.iter()
+			.map(|(s, e)| self.text.slice(*s, *e))
+			.collect::<Vec<String>>()
+			.join("\n");
+
+		if self.selection.kind == SelectKind::Line && !text.ends_with('\n') {
+			text.push('\n');
+		}
+
+		return text;
+
+	}
+
+	pub(super) fn yank_sel(&mut self) {
+		self.store_yank(self.sel_text(), self.selection.kind);
+		self.stop_select();
+	}
+
+	pub(super) fn del_sel(&mut self) {
+
+		let (start, end) = self.sel_bounds();
+		let mut ranges = self.sel_ranges();
+
+		// the last line has no line break to take with it, take the one before
+		if self.selection.kind == SelectKind::Line
+			&& end.line as usize >= self.text.len_lines()
+			&& start.line > 1 {
+			if let Some(r) = ranges.first_mut() {
+				r.0 -= 1;
+			}
+		}
+
+		self.store_yank(self.sel_text(), self.selection.kind);
+
+		for (s, e) in ranges.into_iter().rev() {
+			self.text.remove(s, e);
+		}
+
+		let pos = match self.selection.kind {
+			SelectKind::Char => start,
+			SelectKind::Line => Cursor::new(start.line, 1),
+			SelectKind::Block => Cursor::new(start.line, self.selection.anchor.col.min(self.cursor.col)),
+		};
+
+		self.stop_select();
+		self.move_to(pos);
+		self.highlight();
+
+	}
+
+	pub(super) fn change_sel(&mut self) {
+
+		let kind = self.selection.kind;
+
+		self.del_sel();
+
+		if kind == SelectKind::Line {
+			self.insert_line_at(self.cursor.line - 1);
+			self.move_to(Cursor::new(self.cursor.line, 1));
+		}
+
+		self.mode = Mode::Insert;
+
+	}
+
+	pub(super) fn replace_sel(&mut self, ch: char) {
+
+		let ranges = self.sel_ranges();
+
+		for (s, e) in ranges.into_iter().rev() {
+
+			let replaced = self.text
+				.slice(s, e)
+				.split('\n')
+				.map(|l| graphemes(l).map(|_| ch.to_string()).collect::<String>())
+				.collect::<Vec<String>>()
+				.join("\n");
+
+			self.text.remove(s, e);
+			self.text.insert(s, &replaced);
+
+		}
+
+		self.stop_select();
+		self.move_to(self.sel_bounds().0);
+		self.highlight();
+
+	}
+
+	pub(super) fn indent_sel(&mut self) {
+
+		let (start, end) = self.sel_bounds();
+		let unit = self.conf.indent_unit();
+
+		for ln in start.line..=end.line {
+			if let Some(len) = self.line_len_at(ln) {
+				if len > 0 {
+					let idx = self.pos_to_idx(Cursor::new(ln, 1));
+					self.text.insert(idx, &unit);
+				}
+			}
+		}
+
+		self.move_to(self.cursor);
+		self.highlight();
+
+	}
+
+	pub(super) fn outdent_sel(&mut self) {
+
+		let (start, end) = self.sel_bounds();
+
+		for ln in start.line..=end.line {
+
+			if let Some(line) = self.get_line_at(ln) {
+
+				let n = if line.starts_with('\t') {
+					1
+				} else {
+					line.chars().take(self.conf.indent_size).take_while(|c| *c == ' ').count()
+				};
+
+				if n > 0 {
+					let idx = self.pos_to_idx(Cursor::new(ln, 1));
+					self.text.remove(idx, idx + n);
+				}
+
+			}
+
+		}
+
+		self.move_to(self.cursor);
+		self.highlight();
+
+	}
+
+	pub(super) fn comment_sel(&mut self) {
+		let (start, end) = self.sel_bounds();
+		self.toggle_comment_lines(start.line, end.line);
+		self.stop_select();
+	}
+
+	pub(super) fn block_comment_sel(&mut self) {
+		let (start, end) = self.sel_bounds();
+		let lines = self.selection.kind != SelectKind::Char;
+		self.stop_select();
+		self.toggle_block_comment(start, end, lines);
+	}
+
+}
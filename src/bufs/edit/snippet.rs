@@ -0,0 +1,537 @@
+// wengwengweng
+
+use std::iter::Peekable;
+use std::str::Chars;
+use std::time::SystemTime;
+use serde::Deserialize;
+
+use super::*;
+
+const FNAME: &str = ".space55.snippets.toml";
+
+/// Snippet bodies by trigger word by language, from a file next to the conf.
+///
+/// ```toml
+/// [Rust]
+/// test = "#[test]\nfn ${1:name}() {\n\t$0\n}"
+/// ```
+///
+/// Bodies take `$1` or `${1:placeholder}` tabstops, `$0` for where the cursor ends, and variables like `$TM_FILENAME` or `$CURRENT_YEAR`. The `$CURRENT_*` date is in UTC, there's no time zone to go by.
+#[derive(Deserialize, Clone, Debug, Default)]
+#[serde(transparent)]
+pub struct Snippets {
+	langs: HashMap<String, HashMap<String, String>>,
+}
+
+impl Snippets {
+
+	/// None if there's no snippets file.
+	pub fn load() -> Result<Option<Self>> {
+
+		let path = Conf::path()?.with_file_name(FNAME);
+
+		if !path.exists() {
+			return Ok(None);
+		}
+
+		let content = std::fs::read_to_string(&path)
+			.map_err(|_| format!("failed to read {}", path.display()))?;
+
+		return toml::from_str::<Self>(&content)
+			.map(Some)
+			.map_err(|e| format!("failed to parse {}: {}", path.display(), e));
+
+	}
+
+	fn get(&self, lang: &str, trigger: &str) -> Option<&String> {
+		return self.langs.get(lang)?.get(trigger);
+	}
+
+}
+
+/// An expanded snippet whose tabstops are being filled in.
+#[derive(Clone, Debug)]
+pub struct SnippetState {
+	// char ranges of each tabstop in order with $0 last, the first range is the one typed in and the rest mirror it
+	stops: Vec<Vec<(usize, usize)>>,
+	cur: usize,
+	// text length when last synced, what it changed by went into the current tabstop
+	len: usize,
+	// still has the placeholder, typing replaces it
+	fresh: bool,
+}
+
+#[derive(Clone, Debug, PartialEq)]
+enum Piece {
+	Text(String),
+	Stop(usize, String),
+	Var(String, String),
+}
+
+fn parse_snippet(body: &str) -> Vec<Piece> {
+
+	let mut pieces = vec![];
+	let mut text = String::new();
+	let mut chars = body.chars().peekable();
+
+	while let Some(ch) = chars.next() {
+
+		match ch {
+			'\\' => {
+				match chars.peek() {
+					Some('$') | Some('}') | Some('\\') => text.push(chars.next().unwrap_or(ch)),
+					_ => text.push(ch),
+				}
+			},
+			'$' => {
+				match parse_field(&mut chars) {
+					Some(p) => {
+						if !text.is_empty() {
+							pieces.push(Piece::Text(std::mem::replace(&mut text, String::new())));
+						}
+						pieces.push(p);
+					},
+					None => text.push(ch),
+				}
+			},
+			_ => text.push(ch),
+		}
+
+	}
+
+	if !text.is_empty() {
+		pieces.push(Piece::Text(text));
+	}
+
+	return pieces;
+
+}
+
+// what comes after a '$', leaves the chars alone if it's not a tabstop or variable
+fn parse_field(chars: &mut Peekable<Chars>) -> Option<Piece> {
+
+	let mut c = chars.clone();
+	let braced = c.peek() == Some(&'{');
+
+	if braced {
+		c.next();
+	}
+
+	let digits = c.peek().map(|ch| ch.is_ascii_digit()).unwrap_or(false);
+	let mut name = String::new();
+
+	while let Some(ch) = c.peek() {
+		let ok = if digits { ch.is_ascii_digit() } else { ch.is_ascii_alphanumeric() || *ch == '_' };
+		if !ok {
+			break;
+		}
+		name.push(*ch);
+		c.next();
+	}
+
+	if name.is_empty() {
+		return None;
+	}
+
+	let mut default = String::new();
+
+	if braced {
+		match c.next()? {
+			'}' => {},
+			':' => {
+				loop {
+					match c.next()? {
+						'}' => break,
+						'\\' if matches!(c.peek(), Some('$') | Some('}') | Some('\\')) => default.push(c.next()?),
+						ch => default.push(ch),
+					}
+				}
+			},
+			_ => return None,
+		}
+	}
+
+	*chars = c;
+
+	return Some(match name.parse::<usize>() {
+		Ok(n) if digits => Piece::Stop(n, default),
+		_ => Piece::Var(name, default),
+	});
+
+}
+
+// year, month and day in utc
+fn today() -> (i64, i64, i64) {
+
+	let secs = SystemTime::now()
+		.duration_since(SystemTime::UNIX_EPOCH)
+		.map(|d| d.as_secs())
+		.unwrap_or(0) as i64;
+
+	return civil_date(secs);
+
+}
+
+// year, month and day of a unix time
+fn civil_date(secs: i64) -> (i64, i64, i64) {
+
+	// days to a civil date, from Howard Hinnant's date algorithms
+	let z = secs.div_euclid(86400) + 719468;
+	let era = z.div_euclid(146097);
+	let doe = z - era * 146097;
+	let yoe = (doe - doe / 1460 + doe / 36524 - doe / 146096) / 365;
+	let doy = doe - (365 * yoe + yoe / 4 - yoe / 100);
+	let mp = (5 * doy + 2) / 153;
+	let d = doy - (153 * mp + 2) / 5 + 1;
+	let m = if mp < 10 { mp + 3 } else { mp - 9 };
+	let y = yoe + era * 400 + if m <= 2 { 1 } else { 0 };
+
+	return (y, m, d);
+
+}
+
+// move every range starting at or after a char but one by how much the text there changed
+fn shift_stops(stops: &mut Vec<Vec<(usize, usize)>>, skip: (usize, usize), at: usize, delta: isize) {
+	for (i, ranges) in stops.iter_mut().enumerate() {
+		for (j, r) in ranges.iter_mut().enumerate() {
+			if (i, j) != skip && r.0 >= at {
+				r.0 = (r.0 as isize + delta) as usize;
+				r.1 = (r.1 as isize + delta) as usize;
+			}
+		}
+	}
+}
+
+impl TextEditor {
+
+	fn snippet_var(&self, name: &str) -> Option<String> {
+
+		let (y, m, d) = today();
+		let file_name = |p: Option<&std::ffi::OsStr>| p.map(|n| n.to_string_lossy().to_string());
+
+		return match name {
+			"TM_FILENAME" => file_name(self.path.file_name()),
+			"TM_FILENAME_BASE" => file_name(self.path.file_stem()),
+			"TM_DIRECTORY" => self.path.parent().map(|p| p.display().to_string()),
+			"TM_FILEPATH" => Some(self.path.display().to_string()),
+			"TM_LINE_NUMBER" => Some(self.cursor.line.to_string()),
+			"CURRENT_YEAR" => Some(format!("{}", y)),
+			"CURRENT_YEAR_SHORT" => Some(format!("{:02}", y % 100)),
+			"CURRENT_MONTH" => Some(format!("{:02}", m)),
+			"CURRENT_DATE" => Some(format!("{:02}", d)),
+			_ => None,
+		};
+
+	}
+
+	/// Replace the word before the cursor with the snippet it's the trigger of, false if it's not one.
+	pub(super) fn expand_snippet(&mut self) -> bool {
+
+		if self.mode != Mode::Insert {
+			return false;
+		}
+
+		let lang = match &self.lang {
+			Some(lang) => lang.clone(),
+			None => return false,
+		};
+
+		let start = self.word_start();
+		let trigger = self.completion_prefix(start);
+
+		let body = match self.snippets.get(&lang, &trigger) {
+			Some(body) => body.clone(),
+			None => return false,
+		};
+
+		let pieces = parse_snippet(&body);
+
+		// mirrors all get the text of the first placeholder with some
+		let mut defaults = hmap![];
+
+		for p in &pieces {
+			if let Piece::Stop(n, d) = p {
+				if !d.is_empty() {
+					defaults.entry(*n).or_insert(d.clone());
+				}
+			}
+		}
+
+		// lines after the first line up with it, tabs are the file's indent
+		let line = self.cur_line().unwrap_or(String::new());
+		let indent = line.chars().take_while(|c| *c == ' ' || *c == '\t').collect::<String>();
+		let unit = self.conf.indent_unit();
+		let fix = |s: &str| s.replace('\t', &unit).replace('\n', &format!("\n{}", indent));
+
+		let si = self.pos_to_idx(start);
+		let mut text = String::new();
+		let mut len = 0;
+		let mut stops = bmap![];
+
+		for p in pieces {
+
+			let s = match p {
+				Piece::Text(t) => fix(&t),
+				Piece::Var(name, d) => {
+					match self.snippet_var(&name) {
+						Some(v) => fix(&v),
+						None if !d.is_empty() => fix(&d),
+						// left in so a typo shows
+						None => format!("${}", name),
+					}
+				},
+				Piece::Stop(n, _) => {
+					let d = fix(defaults.get(&n).map(String::as_str).unwrap_or(""));
+					let dlen = d.chars().count();
+					stops.entry(n).or_insert(vec![]).push((si + len, si + len + dlen));
+					d
+				},
+			};
+
+			len += s.chars().count();
+			text.push_str(&s);
+
+		}
+
+		// $0 goes last, the end if there isn't one
+		let last = stops.remove(&0).unwrap_or(vec![(si + len, si + len)]);
+		let mut stops = stops.into_iter().map(|(_, r)| r).collect::<Vec<Vec<(usize, usize)>>>();
+
+		stops.push(last);
+
+		self.text.remove(si, self.pos_to_idx(self.cursor));
+		self.text.insert(si, &text);
+
+		self.snippet = Some(SnippetState {
+			stops: stops,
+			cur: 0,
+			len: self.text.len_chars(),
+			fresh: true,
+		});
+
+		self.goto_tabstop(0);
+		self.highlight();
+
+		return true;
+
+	}
+
+	// put the cursor at the end of a tabstop, the snippet's done at $0
+	fn goto_tabstop(&mut self, i: usize) {
+
+		let mut s = match self.snippet.take() {
+			Some(s) => s,
+			None => return,
+		};
+
+		let (_, end) = s.stops[i][0];
+
+		self.move_to(self.idx_to_pos(end));
+
+		if i + 1 < s.stops.len() {
+			s.cur = i;
+			s.fresh = true;
+			self.snippet = Some(s);
+		}
+
+	}
+
+	/// Go to the next tabstop, or the last one, false if there's no snippet being filled in.
+	pub(super) fn next_tabstop(&mut self, back: bool) -> bool {
+
+		self.sync_snippet();
+
+		let cur = match &self.snippet {
+			Some(s) => s.cur,
+			None => return false,
+		};
+
+		if back {
+			self.goto_tabstop(cur.saturating_sub(1));
+		} else {
+			self.goto_tabstop(cur + 1);
+		}
+
+		return true;
+
+	}
+
+	/// Before a char is typed, takes out the placeholder it replaces.
+	pub(super) fn snippet_typing(&mut self) {
+
+		self.sync_snippet();
+
+		let mut s = match self.snippet.take() {
+			Some(s) => s,
+			None => return,
+		};
+
+		let (start, end) = s.stops[s.cur][0];
+
+		if s.fresh && start != end && self.pos_to_idx(self.cursor) == end {
+			self.text.remove(start, end);
+			shift_stops(&mut s.stops, (s.cur, 0), end, start as isize - end as isize);
+			s.stops[s.cur][0].1 = start;
+			s.len = self.text.len_chars();
+			self.move_to(self.idx_to_pos(start));
+		}
+
+		s.fresh = false;
+		self.snippet = Some(s);
+
+	}
+
+	/// Grow the current tabstop by what was typed in it and copy it to its mirrors.
+	///
+	/// The snippet's done when the text changed anywhere else or insert mode is left.
+	pub(super) fn sync_snippet(&mut self) {
+
+		let mut s = match self.snippet.take() {
+			Some(s) => s,
+			None => return,
+		};
+
+		if self.mode != Mode::Insert {
+			return;
+		}
+
+		let delta = self.text.len_chars() as isize - s.len as isize;
+
+		if delta == 0 {
+			self.snippet = Some(s);
+			return;
+		}
+
+		let (start, end) = s.stops[s.cur][0];
+		let new_end = end as isize + delta;
+		let cur = self.pos_to_idx(self.cursor) as isize;
+
+		if new_end < start as isize || cur < start as isize || cur > new_end {
+			return;
+		}
+
+		let new_end = new_end as usize;
+
+		shift_stops(&mut s.stops, (s.cur, 0), end, delta);
+		s.stops[s.cur][0].1 = new_end;
+		s.fresh = false;
+
+		let text = self.text.slice(start, new_end);
+		let tlen = text.chars().count();
+
+		for m in 1..s.stops[s.cur].len() {
+			let (ms, me) = s.stops[s.cur][m];
+			self.text.remove(ms, me);
+			self.text.insert(ms, &text);
+			shift_stops(&mut s.stops, (s.cur, m), me, tlen as isize - (me - ms) as isize);
+			s.stops[s.cur][m].1 = ms + tlen;
+		}
+
+		s.len = self.text.len_chars();
+		self.snippet = Some(s);
+		self.highlight();
+
+	}
+
+	/// Columns of the current tabstop and its mirrors on a line.
+	pub(super) fn tabstop_cols_at(&self, ln: Line) -> Vec<(Col, Col)> {
+
+		let s = match &self.snippet {
+			Some(s) => s,
+			None => return vec![],
+		};
+
+		return s.stops[s.cur]
+			.iter()
+			.map(|(start, end)| (self.idx_to_pos(*start), self.idx_to_pos(*end)))
+			.filter(|(p1, p2)| p1.line == ln && p2.line == ln)
+			.map(|(p1, p2)| (p1.col, p2.col))
+			.collect();
+
+	}
+
+}
+
+#[cfg(test)]
+mod tests {
+
+	use super::*;
+
+	fn text(s: &str) -> Piece {
+		return Piece::Text(String::from(s));
+	}
+
+	fn stop(n: usize, d: &str) -> Piece {
+		return Piece::Stop(n, String::from(d));
+	}
+
+	fn var(name: &str, d: &str) -> Piece {
+		return Piece::Var(String::from(name), String::from(d));
+	}
+
+	#[test]
+	fn stops() {
+		assert_eq!(parse_snippet("fn ${1:name}($2) {\n\t$0\n}"), vec![
+			text("fn "),
+			stop(1, "name"),
+			text("("),
+			stop(2, ""),
+			text(") {\n\t"),
+			stop(0, ""),
+			text("\n}"),
+		]);
+		assert_eq!(parse_snippet("$12a"), vec![stop(12, ""), text("a")]);
+	}
+
+	#[test]
+	fn escapes() {
+		assert_eq!(parse_snippet("\\$1 \\$ \\} \\\\ \\n"), vec![text("$1 $ } \\ \\n")]);
+		assert_eq!(parse_snippet("${1:a\\}b\\$c}"), vec![stop(1, "a}b$c")]);
+		assert_eq!(parse_snippet("${1:{a}}"), vec![stop(1, "{a"), text("}")]);
+	}
+
+	#[test]
+	fn vars() {
+		assert_eq!(parse_snippet("$TM_FILENAME ${NOPE:x}$CURRENT_YEAR_SHORT"), vec![
+			var("TM_FILENAME", ""),
+			text(" "),
+			var("NOPE", "x"),
+			var("CURRENT_YEAR_SHORT", ""),
+		]);
+	}
+
+	#[test]
+	fn not_fields() {
+		assert_eq!(parse_snippet("$ $- ${} ${1 ${1x}"), vec![text("$ $- ${} ${1 ${1x}")]);
+		assert_eq!(parse_snippet("${1:open"), vec![text("${1:open")]);
+		assert_eq!(parse_snippet("a$"), vec![text("a$")]);
+	}
+
+	#[test]
+	fn shift() {
+
+		let mut stops = vec![vec![(2, 5), (10, 13)], vec![(7, 7)], vec![(0, 0)]];
+
+		// the first range of $1 grew by 2, the rest after it move along
+		shift_stops(&mut stops, (0, 0), 5, 2);
+
+		assert_eq!(stops, vec![vec![(2, 5), (12, 15)], vec![(9, 9)], vec![(0, 0)]]);
+
+		shift_stops(&mut stops, (0, 1), 12, -1);
+
+		assert_eq!(stops, vec![vec![(2, 5), (12, 15)], vec![(9, 9)], vec![(0, 0)]]);
+
+	}
+
+	#[test]
+	fn dates() {
+		assert_eq!(civil_date(0), (1970, 1, 1));
+		assert_eq!(civil_date(86399), (1970, 1, 1));
+		assert_eq!(civil_date(-1), (1969, 12, 31));
+		assert_eq!(civil_date(951782400), (2000, 2, 29));
+		assert_eq!(civil_date(951868800), (2000, 3, 1));
+		assert_eq!(civil_date(1709164800), (2024, 2, 29));
+		assert_eq!(civil_date(4102444799), (2099, 12, 31));
+	}
+
+}
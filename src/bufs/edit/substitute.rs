@@ -0,0 +1,296 @@
+// wengwengweng
+
+use std::collections::VecDeque;
+use regex::Regex;
+use regex::RegexBuilder;
+
+use super::*;
+
+/// A parsed `s/pattern/replacement/flags` command.
+///
+/// The replacement can reference capture groups with `$1` or `${name}`. Flags are `g` to replace every match in a line instead of the first one, `c` to confirm each match, `i` to ignore case and `I` to match case.
+#[derive(Clone, Debug)]
+pub struct Substitute {
+	re: Regex,
+	rep: String,
+	global: bool,
+	confirm: bool,
+}
+
+impl Substitute {
+
+	pub fn parse(s: &str) -> Result<Self> {
+
+		let parts = split_unescaped(s.strip_prefix("s/").ok_or_else(|| format!("expected s/pattern/replacement/"))?);
+
+		let (pat, rep, flags) = match parts.as_slice() {
+			[pat, rep] => (pat, rep, ""),
+			[pat, rep, flags] => (pat, rep, flags.as_str()),
+			_ => return Err(format!("expected s/pattern/replacement/")),
+		};
+
+		let mut global = false;
+		let mut confirm = false;
+		let mut icase = false;
+
+		for f in flags.chars() {
+			match f {
+				'g' => global = true,
+				'c' => confirm = true,
+				'i' => icase = true,
+				'I' => icase = false,
+				_ => return Err(format!("unknown flag '{}'", f)),
+			}
+		}
+
+		let re = RegexBuilder::new(pat)
+			.case_insensitive(icase)
+			.build()
+			.map_err(|_| format!("invalid pattern '{}'", pat))?;
+
+		return Ok(Self {
+			re: re,
+			rep: unescape(rep),
+			global: global,
+			confirm: confirm,
+		});
+
+	}
+
+	/// Matches in a line as char offsets, with the replacement for each.
+	fn matches(&self, line: &str) -> VecDeque<Match> {
+
+		let n = if self.global { usize::MAX } else { 1 };
+
+		return self.re
+			.captures_iter(line)
+			.take(n)
+			.filter_map(|caps| {
+				let m = caps.get(0)?;
+				let mut rep = String::new();
+				caps.expand(&self.rep, &mut rep);
+				return Some(Match {
+					start: line[..m.start()].chars().count(),
+					end: line[..m.end()].chars().count(),
+					rep: rep,
+				});
+			})
+			.collect();
+
+	}
+
+}
+
+// split on '/', keeping "\/" as a literal '/'
+fn split_unescaped(s: &str) -> Vec<String> {
+
+	let mut parts = vec![String::new()];
+	let mut chars = s.chars().peekable();
+
+	while let Some(ch) = chars.next() {
+		match ch {
+			'\\' if chars.peek() == Some(&'/') => {
+				chars.next();
+				parts.last_mut().map(|p| p.push('/'));
+			},
+			'\\' => {
+				parts.last_mut().map(|p| p.push('\\'));
+				if let Some(next) = chars.next() {
+					parts.last_mut().map(|p| p.push(next));
+				}
+			},
+			'/' => parts.push(String::new()),
+			_ => {
+				parts.last_mut().map(|p| p.push(ch));
+			},
+		}
+	}
+
+	return parts;
+
+}
+
+fn unescape(s: &str) -> String {
+
+	let mut out = String::with_capacity(s.len());
+	let mut chars = s.chars();
+
+	while let Some(ch) = chars.next() {
+		if ch == '\\' {
+			match chars.next() {
+				Some('n') => out.push('\n'),
+				Some('t') => out.push('\t'),
+				Some(c) => out.push(c),
+				None => out.push('\\'),
+			}
+		} else {
+			out.push(ch);
+		}
+	}
+
+	return out;
+
+}
+
+#[derive(Clone, Debug)]
+struct Match {
+	start: usize,
+	end: usize,
+	rep: String,
+}
+
+/// State of a substitution waiting for the user to confirm each match.
+pub struct Confirm {
+	sub: Substitute,
+	line: Line,
+	end: Line,
+	matches: VecDeque<Match>,
+	// chars added to the current line by replacements so far
+	shift: isize,
+}
+
+impl TextEditor {
+
+	/// Run a substitution on lines `l1` to `l2`.
+	pub(super) fn substitute(&mut self, sub: Substitute, l1: Line, l2: Line) {
+
+		if sub.confirm {
+			self.confirm = Some(Confirm {
+				sub: sub,
+				line: l1 - 1,
+				end: l2,
+				matches: VecDeque::new(),
+				shift: 0,
+			});
+			self.next_confirm();
+			return;
+		}
+
+		let mut count = 0;
+
+		// bottom up, so added lines don't shift the ones left
+		for ln in (l1..=l2).rev() {
+
+			let line = match self.get_line_at(ln) {
+				Some(l) => l,
+				None => continue,
+			};
+
+			let start = self.pos_to_idx(Cursor::new(ln, 1));
+
+			for m in sub.matches(&line).into_iter().rev() {
+				self.text.remove(start + m.start, start + m.end);
+				self.text.insert(start + m.start, &m.rep);
+				count += 1;
+			}
+
+		}
+
+		if count > 0 {
+			self.move_to(self.line_start_at(Cursor::new(l1, 1)));
+			self.highlight();
+		}
+
+	}
+
+	// move to the next match, or finish
+	fn next_confirm(&mut self) {
+
+		loop {
+
+			let c = match &mut self.confirm {
+				Some(c) => c,
+				None => return,
+			};
+
+			if let Some(m) = c.matches.front() {
+				let idx = self.text.pos_to_char(c.line as usize - 1, 0) as isize + c.shift + m.start as isize;
+				let pos = self.idx_to_pos(idx.max(0) as usize);
+				self.move_to(pos);
+				return;
+			}
+
+			c.line += 1;
+			c.shift = 0;
+
+			if c.line > c.end {
+				self.confirm = None;
+				return;
+			}
+
+			let ln = c.line;
+			let line = self.get_line_at(ln).unwrap_or(String::new());
+
+			if let Some(c) = &mut self.confirm {
+				c.matches = c.sub.matches(&line);
+			}
+
+		}
+
+	}
+
+	/// Replace the current match, or skip it.
+	pub(super) fn confirm_match(&mut self, replace: bool) {
+
+		let c = match &mut self.confirm {
+			Some(c) => c,
+			None => return,
+		};
+
+		let m = match c.matches.pop_front() {
+			Some(m) => m,
+			None => return,
+		};
+
+		if replace {
+
+			let start = (self.text.pos_to_char(c.line as usize - 1, 0) as isize + c.shift) as usize;
+
+			self.text.remove(start + m.start, start + m.end);
+			self.text.insert(start + m.start, &m.rep);
+
+			let lines = m.rep.matches('\n').count() as Line;
+
+			if lines > 0 {
+				// the rest of the line moved down, after the last line of the replacement
+				let tail = m.rep.rsplit('\n').next().unwrap_or("").chars().count();
+				c.line += lines;
+				c.end += lines;
+				c.shift = tail as isize - m.end as isize;
+			} else {
+				c.shift += m.rep.chars().count() as isize - (m.end - m.start) as isize;
+			}
+
+			self.highlight();
+
+		}
+
+		self.next_confirm();
+
+	}
+
+	pub(super) fn confirm_all(&mut self) {
+		while self.confirm.is_some() {
+			self.confirm_match(true);
+		}
+	}
+
+	/// Grapheme cols of the match waiting for confirmation on a line, as [start, end).
+	pub(super) fn confirm_cols_at(&self, ln: Line) -> Option<(Col, Col)> {
+
+		let c = self.confirm.as_ref()?;
+
+		if ln != c.line {
+			return None;
+		}
+
+		let m = c.matches.front()?;
+		let line = self.get_line_at(ln)?;
+		let start = (m.start as isize + c.shift).max(0) as usize;
+		let end = (m.end as isize + c.shift).max(0) as usize;
+
+		return Some((char_to_col(&line, start) as Col + 1, char_to_col(&line, end) as Col + 1));
+
+	}
+
+}
@@ -0,0 +1,202 @@
+// wengwengweng
+
+use std::time::Instant;
+use std::time::Duration;
+
+use super::*;
+
+const SWAP_DIR: &str = "swap";
+const BACKUP_DIR: &str = "backup";
+// how often unsaved changes are written to the swap file
+const SWAP_INTERVAL: Duration = Duration::from_secs(4);
+
+// a file per path, named after the hash of where it really is, the directory is resolved since the file might not exist yet
+fn swap_path(path: impl AsRef<Path>) -> Result<PathBuf> {
+
+	let path = path.as_ref();
+	let path = match (path.parent().and_then(|p| std::fs::canonicalize(p).ok()), path.file_name()) {
+		(Some(dir), Some(name)) => dir.join(name),
+		_ => path.to_path_buf(),
+	};
+
+	let name = format!("{:016x}.swap", hash(path.to_string_lossy().as_bytes()));
+
+	return Ok(data_dir(SWAP_DIR)?.join(name));
+
+}
+
+// the first line is the path of the file, then its content
+fn read_swap(swap: impl AsRef<Path>) -> Option<(PathBuf, String)> {
+	let content = std::fs::read_to_string(swap).ok()?;
+	let mut parts = content.splitn(2, '\n');
+	let path = PathBuf::from(parts.next()?);
+	return Some((path, parts.next().unwrap_or("").to_string()));
+}
+
+/// Files that have swap files left, from a crash or another window.
+pub fn swapped_files() -> Vec<PathBuf> {
+
+	let dir = match data_dir(SWAP_DIR) {
+		Ok(dir) => dir,
+		Err(_) => return vec![],
+	};
+
+	let entries = match std::fs::read_dir(dir) {
+		Ok(entries) => entries,
+		Err(_) => return vec![],
+	};
+
+	return entries
+		.filter_map(|e| e.ok())
+		.filter_map(|e| read_swap(e.path()))
+		.map(|(path, _)| path)
+		.collect();
+
+}
+
+/// When the swap file was last written and what it had.
+#[derive(Clone, Debug)]
+pub struct SwapState {
+	time: Instant,
+	hash: Option<u64>,
+}
+
+impl SwapState {
+	pub fn new() -> Self {
+		return Self {
+			time: Instant::now(),
+			hash: None,
+		};
+	}
+}
+
+impl TextEditor {
+
+	/// Write unsaved changes to the swap file every once in a while.
+	pub(super) fn update_swap(&mut self) {
+
+		if self.scratch || self.swap.time.elapsed() < SWAP_INTERVAL || self.conflict.is_some() {
+			return;
+		}
+
+		self.swap.time = Instant::now();
+
+		if !self.is_modified() {
+			return;
+		}
+
+		self.write_swap();
+
+	}
+
+	// a pending recovery is left alone since it has what the buffer doesn't
+	fn write_swap(&mut self) {
+
+		if self.scratch || self.conflict == Some(Conflict::Swap) {
+			return;
+		}
+
+		let content = self.content();
+		let h = hash(content.as_bytes());
+
+		if self.swap.hash == Some(h) {
+			return;
+		}
+
+		let res = swap_path(&self.path)
+			.and_then(|swap| write_atomic(swap, format!("{}\n{}", self.path.display(), content).as_bytes()));
+
+		match res {
+			Ok(_) => self.swap.hash = Some(h),
+			Err(e) => self.log.push(Msg::error(&e)),
+		}
+
+	}
+
+	/// Keep unsaved changes in the swap file when the buffer goes away, or remove it if there are none.
+	pub(super) fn close_swap(&mut self) {
+		match self.conflict {
+			Some(Conflict::Swap) => {},
+			_ if self.is_modified() => self.write_swap(),
+			_ => self.remove_swap(),
+		}
+	}
+
+	pub(super) fn remove_swap(&mut self) {
+		self.swap.hash = None;
+		if self.scratch {
+			return;
+		}
+		if let Ok(swap) = swap_path(&self.path) {
+			std::fs::remove_file(swap).ok();
+		}
+	}
+
+	/// A swap file with something different from the file means changes that were never saved.
+	pub(super) fn check_swap(&mut self) {
+
+		if self.scratch {
+			return;
+		}
+
+		let swap = match swap_path(&self.path).ok().and_then(read_swap) {
+			Some((_, swap)) => swap,
+			None => return,
+		};
+
+		if swap == self.content() {
+			self.remove_swap();
+		} else {
+			self.conflict = Some(Conflict::Swap);
+		}
+
+	}
+
+	/// Replace the text with what's in the swap file, as unsaved changes.
+	pub(super) fn recover_swap(&mut self) -> Result<()> {
+
+		let (_, content) = swap_path(&self.path)
+			.ok()
+			.and_then(read_swap)
+			.ok_or_else(|| format!("failed to read swap file"))?;
+
+		self.commit();
+
+		let len = self.text.len_chars();
+
+		self.text.remove(0, len);
+		self.text.insert(0, &content);
+		self.commit();
+		self.conflict = None;
+		self.swap.hash = Some(hash(content.as_bytes()));
+		self.move_to(self.cursor);
+		self.highlight();
+		self.log.push(Msg::success(&format!("recovered {}", self.path.display())));
+
+		return Ok(());
+
+	}
+
+	/// Copy the file as it is on disk to the backup dir, before it's overwritten.
+	pub(super) fn backup(&self) -> Result<()> {
+
+		if !self.path.exists() {
+			return Ok(());
+		}
+
+		let name = self.path
+			.file_name()
+			.map(|n| n.to_string_lossy().to_string())
+			.unwrap_or(String::new());
+
+		let backup = data_dir(BACKUP_DIR)?
+			.join(format!("{:016x}-{}", hash(self.path.to_string_lossy().as_bytes()), name));
+
+		std::fs::copy(&self.path, &backup)
+			.map_err(|_| format!("failed to back up {}", self.path.display()))?;
+
+		return Ok(());
+
+	}
+
+}
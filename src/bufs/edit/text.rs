@@ -0,0 +1,378 @@
+// wengwengweng
+
+use std::fmt;
+use serde::Serialize;
+use serde::Deserialize;
+use ropey::Rope;
+use unicode_segmentation::UnicodeSegmentation;
+use unicode_width::UnicodeWidthStr;
+
+/// Line-oriented text storage backed by a rope, indexed by 0-based lines and grapheme columns.
+#[derive(Clone, Debug)]
+pub struct Text {
+	rope: Rope,
+	edited: Option<Edited>,
+	ops: Vec<Op>,
+	changes: Vec<Change>,
+}
+
+/// An edit at a char index, keeping the text so it can be reverted.
+#[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]
+pub enum Op {
+	Insert(usize, String),
+	Remove(usize, String),
+}
+
+impl Op {
+
+	pub fn invert(&self) -> Self {
+		return match self {
+			Op::Insert(idx, text) => Op::Remove(*idx, text.clone()),
+			Op::Remove(idx, text) => Op::Insert(*idx, text.clone()),
+		};
+	}
+
+	/// Char index after the op is applied.
+	pub fn end(&self) -> usize {
+		return match self {
+			Op::Insert(idx, text) => idx + text.chars().count(),
+			Op::Remove(idx, _) => *idx,
+		};
+	}
+
+	/// Rough memory taken, in bytes.
+	pub fn size(&self) -> usize {
+		return match self {
+			Op::Insert(_, text) | Op::Remove(_, text) => text.len() + std::mem::size_of::<Self>(),
+		};
+	}
+
+}
+
+/// An edit as language servers take them, the range it replaced as lines and utf-16 columns in the text before it, and what went there.
+#[derive(Clone, Debug, PartialEq)]
+pub struct Change {
+	pub start: (usize, usize),
+	pub end: (usize, usize),
+	pub text: String,
+}
+
+/// Lines changed since the last [`Text::take_edited`].
+///
+/// The end is kept as the number of untouched lines at the bottom, so it stays valid when lines are added or removed above it.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct Edited {
+	pub first: usize,
+	pub tail: usize,
+}
+
+impl Edited {
+	pub fn merge(self, other: Self) -> Self {
+		return Self {
+			first: self.first.min(other.first),
+			tail: self.tail.min(other.tail),
+		};
+	}
+}
+
+impl Text {
+
+	pub fn from_str(s: &str) -> Self {
+		return Self {
+			rope: Rope::from_str(s),
+			edited: None,
+			ops: vec![],
+			changes: vec![],
+		};
+	}
+
+	pub fn len_lines(&self) -> usize {
+		return self.rope.len_lines();
+	}
+
+	pub fn len_chars(&self) -> usize {
+		return self.rope.len_chars();
+	}
+
+	/// Content of a line, without the trailing line break.
+	pub fn line(&self, i: usize) -> Option<String> {
+
+		if i >= self.len_lines() {
+			return None;
+		}
+
+		let mut line = self.rope.line(i).to_string();
+
+		if line.ends_with('\n') {
+			line.pop();
+		}
+
+		return Some(line);
+
+	}
+
+	/// Length of a line in graphemes, without the trailing line break.
+	pub fn line_len(&self, i: usize) -> Option<usize> {
+
+		if i >= self.len_lines() {
+			return None;
+		}
+
+		let line = self.rope.line(i);
+
+		// every char is a grapheme in plain ascii
+		if line.len_bytes() == line.len_chars() {
+			let len = line.len_chars();
+			if len > 0 && line.char(len - 1) == '\n' {
+				return Some(len - 1);
+			}
+			return Some(len);
+		}
+
+		return self.line(i).map(|l| graphemes(&l).count());
+
+	}
+
+	pub fn lines(&self) -> impl Iterator<Item = String> + '_ {
+		return (0..self.len_lines()).filter_map(move |i| self.line(i));
+	}
+
+	pub fn grapheme_at(&self, line: usize, col: usize) -> Option<String> {
+		return self.line(line)?
+			.graphemes(true)
+			.nth(col)
+			.map(String::from);
+	}
+
+	pub fn char_at(&self, line: usize, col: usize) -> Option<char> {
+		return self.grapheme_at(line, col)?.chars().next();
+	}
+
+	/// Char index of a line / col position, clamped to the text.
+	pub fn pos_to_char(&self, line: usize, col: usize) -> usize {
+
+		if line >= self.len_lines() {
+			return self.len_chars();
+		}
+
+		let start = self.rope.line_to_char(line);
+
+		if let Some(l) = self.line(line) {
+			return start + col_to_char(&l, col);
+		}
+
+		return start;
+
+	}
+
+	/// Line / col position of a char index, snapped to the start of its grapheme.
+	pub fn char_to_pos(&self, idx: usize) -> (usize, usize) {
+
+		let idx = idx.min(self.len_chars());
+		let line = self.rope.char_to_line(idx);
+		let ch = idx - self.rope.line_to_char(line);
+
+		if let Some(l) = self.line(line) {
+			return (line, char_to_col(&l, ch));
+		}
+
+		return (line, ch);
+
+	}
+
+	pub fn insert(&mut self, idx: usize, text: &str) {
+		let idx = idx.min(self.len_chars());
+		if text.is_empty() {
+			return;
+		}
+		self.record_change(idx, idx, text);
+		self.rope.insert(idx, text);
+		self.mark_edited(idx, idx + text.chars().count());
+		self.record(Op::Insert(idx, text.to_string()));
+	}
+
+	pub fn insert_char(&mut self, idx: usize, ch: char) {
+		let idx = idx.min(self.len_chars());
+		self.record_change(idx, idx, &ch.to_string());
+		self.rope.insert_char(idx, ch);
+		self.mark_edited(idx, idx + 1);
+		self.record(Op::Insert(idx, ch.to_string()));
+	}
+
+	pub fn slice(&self, start: usize, end: usize) -> String {
+		let end = end.min(self.len_chars());
+		let start = start.min(end);
+		return self.rope.slice(start..end).to_string();
+	}
+
+	pub fn remove(&mut self, start: usize, end: usize) {
+		let end = end.min(self.len_chars());
+		let start = start.min(end);
+		if start == end {
+			return;
+		}
+		let text = self.rope.slice(start..end).to_string();
+		self.record_change(start, end, "");
+		self.rope.remove(start..end);
+		self.mark_edited(start, start);
+		self.record(Op::Remove(start, text));
+	}
+
+	pub fn apply(&mut self, op: &Op) {
+		match op {
+			Op::Insert(idx, text) => self.insert(*idx, text),
+			Op::Remove(idx, text) => self.remove(*idx, idx + text.chars().count()),
+		}
+	}
+
+	/// Take the ops applied since the last call.
+	pub fn take_ops(&mut self) -> Vec<Op> {
+		return std::mem::take(&mut self.ops);
+	}
+
+	pub fn has_ops(&self) -> bool {
+		return !self.ops.is_empty();
+	}
+
+	// merge with the last op when typing or deleting continuously
+	fn record(&mut self, op: Op) {
+
+		match (self.ops.last_mut(), &op) {
+			(Some(Op::Insert(i1, t1)), Op::Insert(i2, t2)) if *i2 == *i1 + t1.chars().count() => {
+				t1.push_str(t2);
+				return;
+			},
+			(Some(Op::Remove(i1, t1)), Op::Remove(i2, t2)) if *i2 + t2.chars().count() == *i1 => {
+				t1.insert_str(0, t2);
+				*i1 = *i2;
+				return;
+			},
+			(Some(Op::Remove(i1, t1)), Op::Remove(i2, t2)) if *i2 == *i1 => {
+				t1.push_str(t2);
+				return;
+			},
+			_ => {},
+		}
+
+		self.ops.push(op);
+
+	}
+
+	/// Take the changes since the last call.
+	pub fn take_changes(&mut self) -> Vec<Change> {
+		return std::mem::take(&mut self.changes);
+	}
+
+	// line and utf-16 column of a char index
+	fn utf16_pos(&self, idx: usize) -> (usize, usize) {
+		let line = self.rope.char_to_line(idx);
+		let start = self.rope.line_to_char(line);
+		return (line, self.rope.char_to_utf16_cu(idx) - self.rope.char_to_utf16_cu(start));
+	}
+
+	// before the edit is applied, while the positions are still where they were
+	fn record_change(&mut self, start: usize, end: usize, text: &str) {
+		let change = Change {
+			start: self.utf16_pos(start),
+			end: self.utf16_pos(end),
+			text: text.to_string(),
+		};
+		self.changes.push(change);
+	}
+
+	/// Take the lines changed since the last call.
+	pub fn take_edited(&mut self) -> Option<Edited> {
+		return self.edited.take();
+	}
+
+	// mark the lines spanned by a char range in the current content as edited
+	fn mark_edited(&mut self, start: usize, end: usize) {
+		let first = self.rope.char_to_line(start);
+		let last = self.rope.char_to_line(end.min(self.len_chars()));
+		self.merge_edited(Edited {
+			first: first,
+			tail: self.len_lines() - 1 - last,
+		});
+	}
+
+	fn merge_edited(&mut self, e: Edited) {
+		self.edited = Some(match self.edited {
+			Some(prev) => prev.merge(e),
+			None => e,
+		});
+	}
+
+}
+
+impl PartialEq for Text {
+	fn eq(&self, other: &Self) -> bool {
+		return self.rope == other.rope;
+	}
+}
+
+impl fmt::Display for Text {
+	fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+		for chunk in self.rope.chunks() {
+			f.write_str(chunk)?;
+		}
+		return Ok(());
+	}
+}
+
+pub fn graphemes(s: &str) -> impl Iterator<Item = &str> {
+	return s.graphemes(true);
+}
+
+/// Byte offset of a grapheme column in a line.
+pub fn col_to_byte(s: &str, col: usize) -> usize {
+	return s
+		.grapheme_indices(true)
+		.nth(col)
+		.map(|(i, _)| i)
+		.unwrap_or(s.len());
+}
+
+/// Char offset of a grapheme column in a line.
+pub fn col_to_char(s: &str, col: usize) -> usize {
+	return s[..col_to_byte(s, col)].chars().count();
+}
+
+/// Grapheme column of the grapheme containing a byte offset.
+pub fn byte_to_col(s: &str, byte: usize) -> usize {
+	return s
+		.grapheme_indices(true)
+		.take_while(|(i, g)| i + g.len() <= byte)
+		.count();
+}
+
+/// Grapheme column of the grapheme containing a char offset.
+pub fn char_to_col(s: &str, ch: usize) -> usize {
+	let byte = s
+		.char_indices()
+		.nth(ch)
+		.map(|(i, _)| i)
+		.unwrap_or(s.len());
+	return byte_to_col(s, byte);
+}
+
+/// Display width in cells, wide chars take 2.
+pub fn width(s: &str) -> usize {
+	return s.width();
+}
+
+/// Display column of a grapheme column, with tabs expanded.
+pub fn display_col(s: &str, col: usize, tab_width: usize) -> usize {
+
+	let mut dc = 0;
+
+	for g in graphemes(s).take(col) {
+		if g == "\t" {
+			dc += tab_width - dc % tab_width;
+		} else {
+			dc += width(g);
+		}
+	}
+
+	return dc;
+
+}
@@ -0,0 +1,448 @@
+// wengwengweng
+
+use std::time::SystemTime;
+use std::time::Duration;
+use std::collections::BTreeMap;
+use serde::Serialize;
+use serde::Deserialize;
+
+use super::*;
+
+const UNDO_DIR: &str = "undo";
+
+// oldest revisions are dropped past these
+const MAX_SIZE: usize = 1024 * 1024 * 16;
+const MAX_REVISIONS: usize = 10000;
+
+type RevID = usize;
+
+// a transaction of ops, from the parent state to this one
+#[derive(Serialize, Deserialize)]
+struct Revision {
+	parent: RevID,
+	ops: Vec<Op>,
+	time: SystemTime,
+	// the branch redo follows
+	last_child: Option<RevID>,
+}
+
+/// Branching undo history.
+///
+/// Every revision stores the ops from its parent, so undoing a revision after branching keeps the old branch reachable. Revision ids only go up, so they are also in chronological order.
+#[derive(Serialize, Deserialize)]
+pub struct UndoTree {
+	revs: BTreeMap<RevID, Revision>,
+	root: RevID,
+	cur: RevID,
+	saved: Option<RevID>,
+	last_id: RevID,
+	size: usize,
+}
+
+impl UndoTree {
+
+	pub fn new() -> Self {
+		return Self {
+			revs: bmap![
+				0 => Revision {
+					parent: 0,
+					ops: vec![],
+					time: SystemTime::now(),
+					last_child: None,
+				},
+			],
+			root: 0,
+			cur: 0,
+			saved: Some(0),
+			last_id: 0,
+			size: 0,
+		};
+	}
+
+	/// Load the history saved for a file, if the hash of its content is still the same as when it was saved.
+	pub fn load(path: impl AsRef<Path>, hash: u64) -> Option<Self> {
+
+		let bytes = std::fs::read(undo_path(path).ok()?).ok()?;
+		let file: UndoFile<Self> = serde_json::from_slice(&bytes).ok()?;
+
+		if file.hash != hash {
+			return None;
+		}
+
+		let mut tree = file.tree;
+
+		// the file on disk is at the last saved state
+		tree.cur = tree.saved?;
+
+		let valid = tree.revs.contains_key(&tree.cur)
+			&& tree.revs.contains_key(&tree.root)
+			&& tree.revs.iter().all(|(id, rev)| rev.parent < *id || *id == tree.root);
+
+		if !valid {
+			return None;
+		}
+
+		return Some(tree);
+
+	}
+
+	/// Save the history for a file, with the hash of the content it was last saved with.
+	///
+	/// Without a saved state to load it at or any edits, an old history is removed instead, it's for content that's not there anymore.
+	pub fn save(&self, path: impl AsRef<Path>, hash: u64) -> Result<()> {
+
+		let path = path.as_ref();
+		let file = undo_path(path)?;
+
+		if self.saved.is_none() || self.revs.values().all(|r| r.ops.is_empty()) {
+			std::fs::remove_file(file).ok();
+			return Ok(());
+		}
+
+		let bytes = serde_json::to_vec(&UndoFile {
+			path: path.to_path_buf(),
+			hash: hash,
+			tree: self,
+		}).map_err(|_| format!("failed to save undo history of {}", path.display()))?;
+
+		return write_atomic(file, &bytes);
+
+	}
+
+	pub fn commit(&mut self, ops: Vec<Op>) {
+
+		if ops.is_empty() {
+			return;
+		}
+
+		self.last_id += 1;
+
+		let id = self.last_id;
+
+		self.size += ops.iter().map(|o| o.size()).sum::<usize>();
+
+		if let Some(rev) = self.revs.get_mut(&self.cur) {
+			rev.last_child = Some(id);
+		}
+
+		self.revs.insert(id, Revision {
+			parent: self.cur,
+			ops: ops,
+			time: SystemTime::now(),
+			last_child: None,
+		});
+
+		self.cur = id;
+		self.prune();
+
+	}
+
+	pub fn is_modified(&self) -> bool {
+		return self.saved != Some(self.cur);
+	}
+
+	pub fn mark_saved(&mut self) {
+		self.saved = Some(self.cur);
+	}
+
+	/// Ops to go to the parent revision.
+	pub fn undo(&mut self) -> Option<Vec<Op>> {
+
+		if self.cur == self.root {
+			return None;
+		}
+
+		return Some(self.goto(self.revs[&self.cur].parent));
+
+	}
+
+	/// Ops to go to the last visited child revision.
+	pub fn redo(&mut self) -> Option<Vec<Op>> {
+		let child = self.revs[&self.cur].last_child?;
+		return Some(self.goto(child));
+	}
+
+	/// Ops to go to the next (or previous) sibling branch.
+	pub fn switch_branch(&mut self, forward: bool) -> Option<Vec<Op>> {
+
+		if self.cur == self.root {
+			return None;
+		}
+
+		let parent = self.revs[&self.cur].parent;
+
+		let siblings = self.revs
+			.iter()
+			.filter(|(id, rev)| rev.parent == parent && **id != self.root)
+			.map(|(id, _)| *id)
+			.collect::<Vec<RevID>>();
+
+		let pos = siblings.iter().position(|id| *id == self.cur)?;
+
+		let target = if forward {
+			siblings.get(pos + 1)?
+		} else {
+			siblings.get(pos.checked_sub(1)?)?
+		};
+
+		return Some(self.goto(*target));
+
+	}
+
+	/// Ops to go to the state the text was in some time before (or after) the current revision.
+	pub fn travel(&mut self, secs: u64, forward: bool) -> Option<Vec<Op>> {
+
+		let time = self.revs[&self.cur].time;
+		let d = Duration::from_secs(secs);
+
+		let time = if forward {
+			time.checked_add(d)?
+		} else {
+			time.checked_sub(d).unwrap_or(SystemTime::UNIX_EPOCH)
+		};
+
+		// the latest revision made before that time
+		let target = self.revs
+			.iter()
+			.rev()
+			.find(|(_, rev)| rev.time <= time)
+			.map(|(id, _)| *id)
+			.unwrap_or(self.root);
+
+		if target == self.cur {
+			return None;
+		}
+
+		return Some(self.goto(target));
+
+	}
+
+	// ops to get from the current revision to another one, through their common ancestor
+	fn goto(&mut self, target: RevID) -> Vec<Op> {
+
+		let mut up = self.ancestors(self.cur);
+		let mut down = self.ancestors(target);
+
+		// drop the common part
+		while up.last().is_some() && up.last() == down.last() {
+			up.pop();
+			down.pop();
+		}
+
+		let mut ops = vec![];
+
+		for id in up {
+			ops.extend(self.revs[&id].ops.iter().rev().map(|o| o.invert()));
+		}
+
+		for id in down.into_iter().rev() {
+			let parent = self.revs[&id].parent;
+			if let Some(p) = self.revs.get_mut(&parent) {
+				p.last_child = Some(id);
+			}
+			ops.extend(self.revs[&id].ops.iter().cloned());
+		}
+
+		self.cur = target;
+
+		return ops;
+
+	}
+
+	// a revision and all its ancestors until the root, the root not included
+	fn ancestors(&self, mut id: RevID) -> Vec<RevID> {
+
+		let mut list = vec![];
+
+		while id != self.root {
+			list.push(id);
+			id = self.revs[&id].parent;
+		}
+
+		return list;
+
+	}
+
+	// drop the oldest history until it's within limits, never past the current revision
+	fn prune(&mut self) {
+
+		while (self.size > MAX_SIZE || self.revs.len() > MAX_REVISIONS) && self.cur != self.root {
+
+			let children = self.revs
+				.iter()
+				.filter(|(id, rev)| rev.parent == self.root && **id != self.root)
+				.map(|(id, _)| *id)
+				.collect::<Vec<RevID>>();
+
+			let path = self.ancestors(self.cur);
+
+			if children.len() == 1 {
+				// move the root down, the ops from the old root are no longer needed
+				let old = self.root;
+				self.root = children[0];
+				self.remove(old);
+				if let Some(rev) = self.revs.get_mut(&self.root) {
+					self.size -= rev.ops.iter().map(|o| o.size()).sum::<usize>();
+					rev.ops.clear();
+					rev.parent = self.root;
+				}
+			} else if let Some(branch) = children.into_iter().find(|id| !path.contains(id)) {
+				// drop an old branch that doesn't lead to the current state
+				self.remove_subtree(branch);
+			} else {
+				break;
+			}
+
+			if let Some(saved) = self.saved {
+				if !self.revs.contains_key(&saved) {
+					self.saved = None;
+				}
+			}
+
+		}
+
+	}
+
+	fn remove(&mut self, id: RevID) {
+		if let Some(rev) = self.revs.remove(&id) {
+			self.size -= rev.ops.iter().map(|o| o.size()).sum::<usize>();
+		}
+	}
+
+	fn remove_subtree(&mut self, id: RevID) {
+
+		let children = self.revs
+			.iter()
+			.filter(|(cid, rev)| rev.parent == id && **cid != id)
+			.map(|(cid, _)| *cid)
+			.collect::<Vec<RevID>>();
+
+		for c in children {
+			self.remove_subtree(c);
+		}
+
+		self.remove(id);
+
+		for rev in self.revs.values_mut() {
+			if rev.last_child == Some(id) {
+				rev.last_child = None;
+			}
+		}
+
+	}
+
+}
+
+#[derive(Serialize, Deserialize)]
+struct UndoFile<T> {
+	// the file it's the history of, to know when it's gone
+	path: PathBuf,
+	hash: u64,
+	tree: T,
+}
+
+// a file per path, named after the hash of where it really is
+fn undo_path(path: impl AsRef<Path>) -> Result<PathBuf> {
+	let path = path.as_ref();
+	let path = std::fs::canonicalize(path).unwrap_or(path.to_path_buf());
+	return Ok(data_dir(UNDO_DIR)?.join(format!("{:016x}.json", hash(path.to_string_lossy().as_bytes()))));
+}
+
+/// Remove histories of files that are gone or changed since, they'd never be loaded again.
+pub fn prune_undo() {
+
+	let entries = match data_dir(UNDO_DIR).and_then(|d| std::fs::read_dir(d).map_err(|_| format!("failed to read undo dir"))) {
+		Ok(entries) => entries,
+		Err(_) => return,
+	};
+
+	for e in entries.filter_map(|e| e.ok()) {
+
+		let keep = std::fs::read(e.path())
+			.ok()
+			.and_then(|b| serde_json::from_slice::<UndoFile<serde::de::IgnoredAny>>(&b).ok())
+			.and_then(|f| Some(hash(&std::fs::read(&f.path).ok()?) == f.hash))
+			.unwrap_or(false);
+
+		if !keep {
+			std::fs::remove_file(e.path()).ok();
+		}
+
+	}
+
+}
+
+/// FNV-1a, stable across runs unlike the std hasher.
+pub fn hash(bytes: &[u8]) -> u64 {
+
+	let mut h: u64 = 0xcbf29ce484222325;
+
+	for b in bytes {
+		h ^= *b as u64;
+		h = h.wrapping_mul(0x100000001b3);
+	}
+
+	return h;
+
+}
+
+impl TextEditor {
+
+	/// End the current transaction, everything edited since the last commit is undone together.
+	pub(super) fn commit(&mut self) {
+		let ops = self.text.take_ops();
+		self.undo.commit(ops);
+	}
+
+	pub(super) fn is_modified(&self) -> bool {
+		return self.text.has_ops()
+			|| self.undo.is_modified()
+			// converted line endings or charset also need saving
+			|| self.conf.format_label() != self.disk_format;
+	}
+
+	pub(super) fn undo(&mut self) {
+		self.commit();
+		let ops = self.undo.undo();
+		self.apply_ops(ops);
+	}
+
+	pub(super) fn redo(&mut self) {
+		self.commit();
+		let ops = self.undo.redo();
+		self.apply_ops(ops);
+	}
+
+	pub(super) fn switch_branch(&mut self, forward: bool) {
+		self.commit();
+		let ops = self.undo.switch_branch(forward);
+		self.apply_ops(ops);
+	}
+
+	pub(super) fn travel(&mut self, secs: u64, forward: bool) {
+		self.commit();
+		let ops = self.undo.travel(secs, forward);
+		self.apply_ops(ops);
+	}
+
+	// apply ops coming from the undo tree, they're already recorded there
+	fn apply_ops(&mut self, ops: Option<Vec<Op>>) {
+
+		let ops = match ops {
+			Some(ops) => ops,
+			None => return,
+		};
+
+		for op in &ops {
+			self.text.apply(op);
+		}
+
+		self.text.take_ops();
+
+		if let Some(op) = ops.last() {
+			self.move_to(self.idx_to_pos(op.end()));
+		}
+
+	}
+
+}
@@ -0,0 +1,232 @@
+// wengwengweng
+
+use std::time::Instant;
+use std::time::Duration;
+use std::time::SystemTime;
+
+use super::*;
+
+// how often to look at the file on disk
+const CHECK_INTERVAL: Duration = Duration::from_secs(1);
+
+/// The file changed on disk in a way the buffer can't just follow, it has unsaved changes or the file is gone, or there are unsaved changes left in a swap file.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum Conflict {
+	Changed,
+	Deleted,
+	Swap,
+}
+
+/// When the file on disk was last seen, to tell when someone else changed it.
+#[derive(Clone, Debug)]
+pub struct DiskState {
+	pub hash: u64,
+	pub mtime: Option<SystemTime>,
+	checked: Instant,
+}
+
+impl DiskState {
+
+	pub fn new(path: impl AsRef<Path>, bytes: &[u8]) -> Self {
+		return Self {
+			hash: hash(bytes),
+			mtime: mtime(path),
+			checked: Instant::now(),
+		};
+	}
+
+	/// If the file's not what was last read or written, gone doesn't count since there's nothing to lose.
+	pub fn changed(&self, path: impl AsRef<Path>) -> bool {
+		return std::fs::read(path)
+			.map(|bytes| hash(&bytes) != self.hash)
+			.unwrap_or(false);
+	}
+
+}
+
+pub(super) fn mtime(path: impl AsRef<Path>) -> Option<SystemTime> {
+	return std::fs::metadata(path)
+		.and_then(|m| m.modified())
+		.ok();
+}
+
+impl TextEditor {
+
+	/// Look for changes on disk, unmodified buffers are reloaded and modified ones get a conflict.
+	pub(super) fn check_disk(&mut self) {
+
+		if self.scratch || self.conflict.is_some() || self.disk.checked.elapsed() < CHECK_INTERVAL {
+			return;
+		}
+
+		self.disk.checked = Instant::now();
+
+		let mtime = mtime(&self.path);
+
+		if mtime == self.disk.mtime {
+			return;
+		}
+
+		// it's never been saved, nothing to lose
+		if mtime.is_none() && self.disk.mtime.is_none() {
+			return;
+		}
+
+		if mtime.is_none() {
+			self.conflict = Some(Conflict::Deleted);
+			return;
+		}
+
+		// touched but the same
+		if let Ok(bytes) = std::fs::read(&self.path) {
+			if hash(&bytes) == self.disk.hash {
+				self.disk.mtime = mtime;
+				return;
+			}
+		}
+
+		if self.is_modified() {
+			self.conflict = Some(Conflict::Changed);
+		} else {
+			match self.read_disk() {
+				Ok(_) => self.log.push(Msg::info(&format!("reloaded {}", self.path.display()))),
+				Err(e) => self.log.push(Msg::error(&e)),
+			}
+		}
+
+	}
+
+	/// Replace the text with what's on disk, as an edit that can be undone.
+	pub(super) fn read_disk(&mut self) -> Result<()> {
+
+		if self.scratch {
+			return Err(format!("not a file"));
+		}
+
+		let bytes = std::fs::read(&self.path)
+			.map_err(|_| format!("failed to read {}", self.path.display()))?;
+
+		let (content, warnings) = self.conf.decode_file(&bytes);
+
+		for w in warnings {
+			self.log.push(Msg::error(&w));
+		}
+
+		self.commit();
+
+		if self.content() != content {
+			let len = self.text.len_chars();
+			self.text.remove(0, len);
+			self.text.insert(0, &content);
+		}
+
+		self.commit();
+		self.undo.mark_saved();
+		self.remove_swap();
+		self.disk = DiskState::new(&self.path, &bytes);
+		self.disk_format = self.conf.format_label();
+		self.conflict = None;
+		self.move_to(self.cursor);
+		self.highlight();
+		self.git_reload();
+
+		return Ok(());
+
+	}
+
+	/// Keep the buffer as it is, the next save overwrites what's on disk.
+	pub(super) fn keep_buffer(&mut self) {
+
+		let bytes = std::fs::read(&self.path).unwrap_or(vec![]);
+
+		self.disk = DiskState::new(&self.path, &bytes);
+		self.conflict = None;
+
+	}
+
+	/// Open a diff of the file on disk against the buffer.
+	pub(super) fn diff_disk(&mut self) -> Result<()> {
+
+		let mut child = std::process::Command::new("diff")
+			.arg("-u")
+			.arg("--label")
+			.arg("disk")
+			.arg("--label")
+			.arg("buffer")
+			.arg(&self.path)
+			.arg("-")
+			.stdin(std::process::Stdio::piped())
+			.stdout(std::process::Stdio::piped())
+			.spawn()
+			.map_err(|_| format!("failed to run diff"))?;
+
+		if let Some(mut stdin) = child.stdin.take() {
+			let content = format!("{}\n", self.content());
+			std::thread::spawn(move || {
+				use std::io::Write;
+				stdin.write_all(content.as_bytes()).ok();
+			});
+		}
+
+		let out = child
+			.wait_with_output()
+			.map_err(|_| format!("failed to run diff"))?;
+
+		// 1 means there are differences
+		if out.status.code() != Some(0) && out.status.code() != Some(1) {
+			return Err(format!("failed to run diff"));
+		}
+
+		let name = self.path
+			.file_name()
+			.map(|n| n.to_string_lossy().to_string())
+			.unwrap_or(String::new());
+
+		self.actions.push(Action::Scratch(
+			self.path.with_file_name(format!("{}.diff", name)),
+			String::from_utf8_lossy(&out.stdout).to_string(),
+		));
+
+		return Ok(());
+
+	}
+
+	pub(super) fn resolve_conflict(&mut self, ch: char) {
+
+		let conflict = match self.conflict {
+			Some(c) => c,
+			None => return,
+		};
+
+		let res = match (conflict, ch) {
+			(Conflict::Changed, 'r') => self.read_disk(),
+			(Conflict::Changed, 'd') => self.diff_disk(),
+			(Conflict::Swap, 'r') => self.recover_swap(),
+			(Conflict::Swap, 'd') => {
+				self.remove_swap();
+				self.conflict = None;
+				Ok(())
+			},
+			(Conflict::Deleted, 'c') => {
+				if self.is_modified() {
+					Err(format!("unsaved changes"))
+				} else {
+					self.conflict = None;
+					self.actions.push(Action::Close);
+					Ok(())
+				}
+			},
+			(Conflict::Changed, 'k') | (Conflict::Deleted, 'k') => {
+				self.keep_buffer();
+				Ok(())
+			},
+			_ => Ok(()),
+		};
+
+		if let Err(e) = res {
+			self.log.push(Msg::error(&e));
+		}
+
+	}
+
+}
@@ -0,0 +1,327 @@
+// wengwengweng
+
+use super::*;
+
+// rows of a line when it's not wrapped
+const ONE_ROW: [usize; 1] = [0];
+
+/// How lines are wrapped, in cells, `width` is 0 when they aren't.
+#[derive(Clone, Copy, Debug, PartialEq, Default)]
+pub struct WrapState {
+	pub width: usize,
+	tab_width: usize,
+	indent: bool,
+	// width of a cell in pixels, measured from the font
+	pub cell: f32,
+}
+
+fn cell_width(g: &str, col: usize, tab_width: usize) -> usize {
+	if g == "\t" {
+		return tab_width - col % tab_width;
+	}
+	return width(g);
+}
+
+fn indent_cells(line: &str, tab_width: usize) -> usize {
+	let indent = graphemes(line)
+		.take_while(|g| *g == " " || *g == "\t")
+		.count();
+	return display_col(line, indent, tab_width);
+}
+
+/// Char index each display row of a line starts at, breaking after whitespace when a word fits.
+///
+/// Continuation rows are `indent` cells narrower.
+fn wrap_line(line: &str, width: usize, tab_width: usize, indent: usize) -> Vec<usize> {
+
+	let mut gs = vec![];
+	let mut ci = 0;
+
+	for g in graphemes(line) {
+		gs.push((ci, g));
+		ci += g.chars().count();
+	}
+
+	let mut rows = vec![0];
+	let mut start = 0;
+	let mut col = 0;
+	let mut last_break = None;
+
+	for i in 0..gs.len() {
+
+		let g = gs[i].1;
+		let mut gw = cell_width(g, col, tab_width);
+		let budget = if rows.len() == 1 { width } else { width - indent };
+
+		if col + gw > budget && i > start {
+
+			let brk = match last_break {
+				Some(b) if b > start => b,
+				_ => i,
+			};
+
+			rows.push(gs[brk].0);
+			start = brk;
+			last_break = None;
+			col = 0;
+
+			for j in brk..i {
+				col += cell_width(gs[j].1, col, tab_width);
+			}
+
+			gw = cell_width(g, col, tab_width);
+
+		}
+
+		col += gw;
+
+		if g.chars().all(char::is_whitespace) {
+			last_break = Some(i + 1);
+		}
+
+	}
+
+	return rows;
+
+}
+
+impl TextEditor {
+
+	/// Rewrap everything if the width or settings changed, `cols` is how many cells fit in the view.
+	pub(super) fn update_wrap(&mut self, cols: usize, cell: f32) {
+
+		let width = if self.conf.soft_wrap {
+			self.conf.wrap_column.unwrap_or(cols).max(1)
+		} else {
+			0
+		};
+
+		let state = WrapState {
+			width: width,
+			tab_width: self.conf.tab_width,
+			indent: self.conf.wrap_indent,
+			cell: cell,
+		};
+
+		if state != self.wrap {
+			self.wrap = state;
+			self.wrap_rows.clear();
+			self.rewrap(0, 0, self.text.len_lines());
+			self.recount_rows(0);
+		}
+
+	}
+
+	/// Rewrap lines that changed, `first..old_end` in the old rows become `first..new_end` in the text.
+	///
+	/// Display rows aren't counted here, folds have to move along with the lines first.
+	pub(super) fn rewrap(&mut self, first: usize, old_end: usize, new_end: usize) {
+
+		if self.wrap.width == 0 {
+			return;
+		}
+
+		let old_end = old_end.min(self.wrap_rows.len());
+		let first = first.min(old_end);
+		let rows = (first..new_end)
+			.map(|i| {
+				let line = self.text.line(i).unwrap_or(String::new());
+				return wrap_line(&line, self.wrap.width, self.wrap.tab_width, self.wrap_indent(&line));
+			})
+			.collect::<Vec<Vec<usize>>>();
+
+		self.wrap_rows.splice(first..old_end, rows);
+
+	}
+
+	/// Count display rows again from a line down, after lines were rewrapped or folds changed there.
+	pub(super) fn recount_rows(&mut self, from: usize) {
+
+		let len = self.text.len_lines();
+		let from = from.min(len).min(self.row_starts.len().saturating_sub(1));
+
+		self.row_starts.truncate(from + 1);
+
+		let mut row = self.row_starts.pop().unwrap_or(0);
+
+		for i in from..len {
+			self.row_starts.push(row);
+			if !self.is_hidden(i) {
+				row += self.line_rows(i).len();
+			}
+		}
+
+		self.row_starts.push(row);
+
+	}
+
+	// indentation of continuation rows in cells, at most half the width so there's room left
+	fn wrap_indent(&self, line: &str) -> usize {
+		if !self.wrap.indent {
+			return 0;
+		}
+		return indent_cells(line, self.wrap.tab_width).min(self.wrap.width / 2);
+	}
+
+	/// Where each display row of a line starts, as char indices.
+	pub(super) fn line_rows(&self, i: usize) -> &[usize] {
+		if self.wrap.width == 0 {
+			return &ONE_ROW;
+		}
+		return self.wrap_rows
+			.get(i)
+			.map(|r| r.as_slice())
+			.unwrap_or(&ONE_ROW);
+	}
+
+	/// Pixels continuation rows of a line are pushed right.
+	pub(super) fn wrap_indent_x(&self, line: &str) -> f32 {
+		return self.wrap_indent(line) as f32 * self.wrap.cell;
+	}
+
+	// row of a char index within its line
+	fn row_in_line(&self, i: usize, ci: usize) -> usize {
+		let rows = self.line_rows(i);
+		return rows
+			.iter()
+			.rposition(|s| *s <= ci)
+			.unwrap_or(0)
+			.min(rows.len() - 1);
+	}
+
+	/// Display row of a position, counted from the top.
+	pub(super) fn display_row(&self, pos: Cursor) -> usize {
+
+		let i = (pos.line - 1).max(0) as usize;
+
+		if self.wrap.width == 0 && self.folds.is_empty() {
+			return i;
+		}
+
+		let line = self.get_line_at(pos.line).unwrap_or(String::new());
+		let ci = col_to_char(&line, (pos.col - 1).max(0) as usize);
+		// a line in a fold is where the fold is
+		let shown = self.fold_around(i).map(|(s, _)| s).unwrap_or(i);
+		let before = self.row_starts
+			.get(shown)
+			.or(self.row_starts.last())
+			.cloned()
+			.unwrap_or(0);
+
+		return before + self.row_in_line(i, ci);
+
+	}
+
+	/// Line index and row in it of a display row.
+	pub(super) fn row_to_line(&self, row: usize) -> (usize, usize) {
+
+		if self.wrap.width == 0 && self.folds.is_empty() {
+			return (row, 0);
+		}
+
+		let len = self.text.len_lines();
+		let starts = &self.row_starts[..len.min(self.row_starts.len())];
+
+		if row >= self.row_starts.last().cloned().unwrap_or(0) {
+			return (len, 0);
+		}
+
+		// lines in a fold start where the line after it does, so the last line starting at or before the row is the one that's shown
+		let i = starts.partition_point(|r| *r <= row).saturating_sub(1);
+
+		return (i, row - starts.get(i).cloned().unwrap_or(0));
+
+	}
+
+	/// Move to the display row above or below, keeping the cell the cursor is at in its row.
+	///
+	/// Returns false when lines aren't wrapped or folded, so moving by lines applies.
+	pub(super) fn move_row(&mut self, down: bool) -> bool {
+
+		if self.wrap.width == 0 && self.folds.is_empty() {
+			return false;
+		}
+
+		let i = (self.cursor.line - 1).max(0) as usize;
+		let line = self.get_line_at(self.cursor.line).unwrap_or(String::new());
+		let ci = col_to_char(&line, (self.cursor.col - 1).max(0) as usize);
+		let r = self.row_in_line(i, ci);
+		let start = self.line_rows(i)[r];
+
+		// cells from the start of the row
+		let cells = |line: &str, start: usize, ci: usize| {
+			let s = char_to_col(line, start);
+			let e = char_to_col(line, ci);
+			let row = graphemes(line).skip(s).take(e.saturating_sub(s)).collect::<String>();
+			return display_col(&row, e.saturating_sub(s), self.wrap.tab_width);
+		};
+
+		let target = cells(&line, start, ci);
+
+		let (ti, tr) = if down {
+			if r + 1 < self.line_rows(i).len() {
+				(i, r + 1)
+			} else if let Some(n) = self.next_visible(i, true) {
+				(n, 0)
+			} else {
+				return true;
+			}
+		} else {
+			if r > 0 {
+				(i, r - 1)
+			} else if let Some(p) = self.next_visible(i, false) {
+				(p, self.line_rows(p).len() - 1)
+			} else {
+				return true;
+			}
+		};
+
+		let tline = self.get_line_at(ti as Line + 1).unwrap_or(String::new());
+		let rows = self.line_rows(ti);
+		let s = rows[tr];
+		let len = tline.chars().count();
+		// the last grapheme of a row that isn't the last is where the next one starts, so stop before it
+		let e = rows.get(tr + 1).map(|e| e.saturating_sub(1)).unwrap_or(len);
+		let s_col = char_to_col(&tline, s);
+		let e_col = char_to_col(&tline, e);
+
+		let mut col = s_col;
+
+		while col < e_col && cells(&tline, s, col_to_char(&tline, col + 1)) <= target {
+			col += 1;
+		}
+
+		self.move_to(Cursor::new(ti as Line + 1, col as Col + 1));
+
+		return true;
+
+	}
+
+}
+
+/// Part of a rendered line between two char indices.
+pub(super) fn slice_chunks(chunks: &[TextChunk], start: usize, end: usize) -> Vec<TextChunk> {
+
+	let mut out = vec![];
+	let mut pos = 0;
+
+	for c in chunks {
+
+		let len = c.text.chars().count();
+		let s = start.max(pos);
+		let e = end.min(pos + len);
+
+		if s < e {
+			out.push(TextChunk {
+				text: c.text.chars().skip(s - pos).take(e - s).collect(),
+				color: c.color,
+			});
+		}
+
+		pos += len;
+
+	}
+
+	return out;
+
+}
@@ -0,0 +1,174 @@
+// wengwengweng
+
+use super::*;
+
+// state after the last put, to cycle it through the yank history
+pub struct LastPut {
+	text: Text,
+	n: usize,
+	after: bool,
+}
+
+impl TextEditor {
+
+	pub(super) fn store_yank(&mut self, text: String, kind: SelectKind) {
+		self.registers.borrow_mut().set(self.reg.take(), Yank {
+			text: text,
+			kind: kind,
+		});
+	}
+
+	pub(super) fn yank_line(&mut self) {
+		if let Some(line) = self.cur_line() {
+			self.store_yank(format!("{}\n", line), SelectKind::Line);
+		}
+	}
+
+	pub(super) fn put(&mut self, after: bool) {
+
+		let reg = self.reg.take();
+		let y = self.registers.borrow_mut().get(reg);
+
+		if let Some(y) = y {
+			self.put_yank(&y, after);
+			self.last_put = Some(LastPut {
+				text: self.text.clone(),
+				n: reg.and_then(|c| c.to_digit(10)).unwrap_or(0) as usize,
+				after: after,
+			});
+			self.highlight();
+		}
+
+	}
+
+	/// Replace the text just put with the previous entry in yank history.
+	pub(super) fn cycle_put(&mut self) {
+
+		let (n, after) = match &self.last_put {
+			Some(p) if p.text == self.text => (p.n, p.after),
+			_ => return,
+		};
+
+		let len = self.registers.borrow().history_len();
+
+		if len == 0 {
+			return;
+		}
+
+		let n = (n + 1) % len;
+		let y = self.registers.borrow().history(n).cloned();
+
+		if let Some(y) = y {
+			self.undo();
+			self.put_yank(&y, after);
+			self.last_put = Some(LastPut {
+				text: self.text.clone(),
+				n: n,
+				after: after,
+			});
+			self.highlight();
+		}
+
+	}
+
+	fn put_yank(&mut self, y: &Yank, after: bool) {
+
+		let cursor = self.cursor;
+		let len = match self.line_len_at(cursor.line) {
+			Some(len) => len,
+			None => return,
+		};
+
+		match y.kind {
+
+			SelectKind::Char => {
+
+				let pos = if after && cursor.col <= len {
+					Cursor {
+						col: cursor.col + 1,
+						.. cursor
+					}
+				} else {
+					cursor
+				};
+
+				let idx = self.pos_to_idx(pos);
+
+				self.text.insert(idx, &y.text);
+				self.move_to(self.idx_to_pos(idx + y.text.chars().count()));
+
+			},
+
+			SelectKind::Line => {
+
+				let text = y.text.strip_suffix('\n').unwrap_or(&y.text);
+
+				let ln = if after {
+					if cursor.line as usize >= self.text.len_lines() {
+						let end = self.text.len_chars();
+						self.text.insert(end, &format!("\n{}", text));
+					} else {
+						let idx = self.pos_to_idx(Cursor::new(cursor.line + 1, 1));
+						self.text.insert(idx, &format!("{}\n", text));
+					}
+					cursor.line + 1
+				} else {
+					let idx = self.pos_to_idx(Cursor::new(cursor.line, 1));
+					self.text.insert(idx, &format!("{}\n", text));
+					cursor.line
+				};
+
+				self.cursor = self.line_start_at(Cursor::new(ln, 1));
+
+			},
+
+			SelectKind::Block => {
+
+				let col = if after && cursor.col <= len {
+					cursor.col + 1
+				} else {
+					cursor.col
+				};
+
+				let vcol = self.get_line_at(cursor.line)
+					.map(|l| display_col(&l, col as usize - 1, self.conf.tab_width))
+					.unwrap_or(0);
+
+				for (i, piece) in y.text.split('\n').enumerate() {
+
+					let ln = cursor.line + i as Line;
+
+					if ln as usize > self.text.len_lines() {
+						let end = self.text.len_chars();
+						self.text.insert(end, "\n");
+					}
+
+					let line = self.get_line_at(ln).unwrap_or(String::new());
+					let len = graphemes(&line).count();
+					let width = display_col(&line, len, self.conf.tab_width);
+
+					if width < vcol {
+						let idx = self.pos_to_idx(Cursor::new(ln, len as Col + 1));
+						self.text.insert(idx, &format!("{}{}", " ".repeat(vcol - width), piece));
+					} else {
+						let c = (0..=len)
+							.find(|c| display_col(&line, *c, self.conf.tab_width) >= vcol)
+							.unwrap_or(len);
+						let idx = self.pos_to_idx(Cursor::new(ln, c as Col + 1));
+						self.text.insert(idx, piece);
+					}
+
+				}
+
+				self.move_to(Cursor {
+					col: col,
+					.. cursor
+				});
+
+			},
+
+		}
+
+	}
+
+}
@@ -0,0 +1,475 @@
+// wengwengweng
+
+use std::path::Path;
+use std::path::PathBuf;
+
+use kit::textinput::*;
+
+use crate::*;
+
+const LINE_SPACING: f32 = 3.0;
+const FONT_SIZE: f32 = 12.0;
+const LINE_HEIGHT: f32 = FONT_SIZE + LINE_SPACING;
+const ROW_LEN: usize = 16;
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+enum Mode {
+	Normal,
+	Edit,
+	Goto,
+	Search,
+}
+
+/// Bytes of a file in offset, hex and ascii columns.
+pub struct HexViewer {
+	path: PathBuf,
+	data: Vec<u8>,
+	cursor: usize,
+	// first row on screen
+	scroll: usize,
+	view_size: Option<(f32, f32)>,
+	mode: Mode,
+	// high half of a byte typed in edit mode
+	nibble: Option<u8>,
+	bar: Input,
+	search: Option<Vec<u8>>,
+	modified: bool,
+	// what's on disk, to not overwrite changes from somewhere else
+	disk: DiskState,
+	// changed on disk, saving again overwrites it
+	conflict: bool,
+	log: Vec<Msg>,
+}
+
+impl HexViewer {
+
+	pub fn new(path: impl AsRef<Path>, data: Vec<u8>) -> Self {
+
+		let disk = DiskState::new(&path, &data);
+
+		return Self {
+			path: path.as_ref().to_path_buf(),
+			data: data,
+			cursor: 0,
+			scroll: 0,
+			view_size: None,
+			mode: Mode::Normal,
+			nibble: None,
+			bar: Input::new(),
+			search: None,
+			modified: false,
+			disk: disk,
+			conflict: false,
+			log: vec![],
+		};
+
+	}
+
+	fn move_to(&mut self, i: usize) {
+		self.cursor = i.min(self.data.len().saturating_sub(1));
+		self.nibble = None;
+	}
+
+	fn move_by(&mut self, n: isize) {
+		let i = self.cursor as isize + n;
+		if i >= 0 && (i as usize) < self.data.len() {
+			self.move_to(i as usize);
+		}
+	}
+
+	fn open_bar(&mut self, mode: Mode) {
+		self.mode = mode;
+		self.bar = Input::new();
+	}
+
+	fn run_bar(&mut self) -> Result<()> {
+
+		let content = self.bar.content().trim().to_string();
+
+		match self.mode {
+			Mode::Goto => {
+				let offset = parse_offset(&content)?;
+				if offset >= self.data.len() {
+					return Err(format!("offset out of range"));
+				}
+				self.move_to(offset);
+			},
+			Mode::Search => {
+				if content.is_empty() {
+					return Ok(());
+				}
+				self.search = Some(parse_bytes(&content));
+				self.find(true);
+			},
+			_ => {},
+		}
+
+		return Ok(());
+
+	}
+
+	// jump to the next or previous match, wrapping around
+	fn find(&mut self, forward: bool) {
+
+		let pat = match &self.search {
+			Some(pat) if !pat.is_empty() => pat.clone(),
+			_ => return,
+		};
+
+		let len = self.data.len();
+
+		let found = (1..=len)
+			.map(|n| {
+				return if forward {
+					(self.cursor + n) % len
+				} else {
+					(self.cursor + len - n) % len
+				};
+			})
+			.find(|i| self.data[*i..].starts_with(&pat));
+
+		match found {
+			Some(i) => self.move_to(i),
+			None => self.log.push(Msg::error("not found")),
+		}
+
+	}
+
+	fn edit(&mut self, ch: char) {
+
+		let n = match ch.to_digit(16) {
+			Some(n) => n as u8,
+			None => return,
+		};
+
+		if self.data.is_empty() {
+			return;
+		}
+
+		match self.nibble.take() {
+			Some(hi) => {
+				self.data[self.cursor] = hi << 4 | n;
+				self.modified = true;
+				self.move_by(1);
+			},
+			None => self.nibble = Some(n),
+		}
+
+	}
+
+	fn save(&mut self) -> Result<()> {
+
+		if !self.modified {
+			self.log.push(Msg::info("no changes"));
+			return Ok(());
+		}
+
+		if !self.conflict && self.disk.changed(&self.path) {
+			self.conflict = true;
+			return Err(format!("file changed on disk, w again to overwrite it"));
+		}
+
+		write_atomic(&self.path, &self.data)?;
+		self.disk = DiskState::new(&self.path, &self.data);
+		self.conflict = false;
+		self.modified = false;
+		self.log.push(Msg::success(&format!("written to {}", self.path.display())));
+
+		return Ok(());
+
+	}
+
+	fn rows_on_screen(&self, vh: f32) -> usize {
+		return ((vh - FONT_SIZE - LINE_SPACING * 2.0) / LINE_HEIGHT).max(1.0) as usize;
+	}
+
+}
+
+// "0x" for hex, decimal otherwise
+fn parse_offset(s: &str) -> Result<usize> {
+	return match s.strip_prefix("0x") {
+		Some(hex) => usize::from_str_radix(hex, 16),
+		None => s.parse::<usize>(),
+	}.map_err(|_| format!("invalid offset '{}'", s));
+}
+
+// hex bytes like "de ad be ef", or text if that's not what it is
+fn parse_bytes(s: &str) -> Vec<u8> {
+
+	let hex = s
+		.chars()
+		.filter(|c| !c.is_whitespace())
+		.collect::<String>();
+
+	if hex.len() % 2 == 0 && hex.chars().all(|c| c.is_ascii_hexdigit()) {
+		return (0..hex.len())
+			.step_by(2)
+			.filter_map(|i| u8::from_str_radix(&hex[i..i + 2], 16).ok())
+			.collect();
+	}
+
+	return s.as_bytes().to_vec();
+
+}
+
+// the row as text, and where each byte's hex and ascii start in it
+fn format_row(offset: usize, bytes: &[u8]) -> (String, Vec<usize>, Vec<usize>) {
+
+	let mut text = format!("{:08x}  ", offset);
+	let mut hex_pos = vec![];
+	let mut ascii_pos = vec![];
+
+	for i in 0..ROW_LEN {
+		if i == ROW_LEN / 2 {
+			text.push(' ');
+		}
+		match bytes.get(i) {
+			Some(b) => {
+				hex_pos.push(text.len());
+				text.push_str(&format!("{:02x} ", b));
+			},
+			None => text.push_str("   "),
+		}
+	}
+
+	text.push_str(" |");
+
+	for b in bytes {
+		ascii_pos.push(text.len());
+		text.push(if b.is_ascii_graphic() || *b == b' ' { *b as char } else { '.' });
+	}
+
+	text.push('|');
+
+	return (text, hex_pos, ascii_pos);
+
+}
+
+impl Buffer for HexViewer {
+
+	fn path(&self) -> Option<&Path> {
+		return Some(&self.path);
+	}
+
+	fn modified(&self) -> bool {
+		return self.modified;
+	}
+
+	fn closable(&self) -> bool {
+		return !self.modified;
+	}
+
+	fn busy(&self) -> bool {
+		return self.mode != Mode::Normal;
+	}
+
+	fn set_view_size(&mut self, w: f32, h: f32) {
+		self.view_size = Some((w, h));
+	}
+
+	fn log(&mut self) -> Option<&mut Vec<Msg>> {
+		return Some(&mut self.log);
+	}
+
+	fn event(&mut self, _: &mut Ctx, e: &input::Event) -> Result<()> {
+
+		match e {
+
+			Event::KeyPress(k) => {
+				match self.mode {
+					Mode::Normal => {
+						match k {
+							Key::Enter => self.mode = Mode::Edit,
+							Key::W => {
+								if let Err(e) = self.save() {
+									self.log.push(Msg::error(&e));
+								}
+							},
+							_ => {},
+						}
+					},
+					Mode::Edit => {
+						match k {
+							Key::Esc => {
+								self.mode = Mode::Normal;
+								self.nibble = None;
+							},
+							_ => {},
+						}
+					},
+					Mode::Goto | Mode::Search => {
+						match k {
+							Key::Esc => self.mode = Mode::Normal,
+							Key::Enter => {
+								if let Err(e) = self.run_bar() {
+									self.log.push(Msg::error(&e));
+								}
+								self.mode = Mode::Normal;
+							},
+							_ => {},
+						}
+					},
+				}
+			},
+
+			Event::KeyPressRepeat(k) => {
+				match self.mode {
+					Mode::Normal | Mode::Edit => {
+						match k {
+							Key::H | Key::Left => self.move_by(-1),
+							Key::L | Key::Right => self.move_by(1),
+							Key::K | Key::Up => self.move_by(-(ROW_LEN as isize)),
+							Key::J | Key::Down => self.move_by(ROW_LEN as isize),
+							_ => {},
+						}
+					},
+					Mode::Goto | Mode::Search => {
+						match k {
+							Key::Backspace => self.bar.del(),
+							Key::Left => self.bar.move_left(),
+							Key::Right => self.bar.move_right(),
+							_ => {},
+						}
+					},
+				}
+			},
+
+			Event::CharInput(ch) => {
+				match self.mode {
+					Mode::Normal => {
+						match ch {
+							':' => self.open_bar(Mode::Goto),
+							'?' => self.open_bar(Mode::Search),
+							'n' => self.find(true),
+							'N' => self.find(false),
+							'<' => self.move_to(self.cursor - self.cursor % ROW_LEN),
+							'>' => self.move_to(self.cursor - self.cursor % ROW_LEN + ROW_LEN - 1),
+							_ => {},
+						}
+					},
+					Mode::Edit => self.edit(*ch),
+					Mode::Goto | Mode::Search => self.bar.insert(*ch),
+				}
+			},
+
+			_ => {},
+
+		}
+
+		return Ok(());
+
+	}
+
+	fn update(&mut self, d: &mut Ctx) -> Result<()> {
+
+		let (_, vh) = self.view_size.unwrap_or((d.gfx.width() as f32, d.gfx.height() as f32));
+		let rows = self.rows_on_screen(vh);
+		let row = self.cursor / ROW_LEN;
+
+		if row < self.scroll {
+			self.scroll = row;
+		} else if row >= self.scroll + rows {
+			self.scroll = row + 1 - rows;
+		}
+
+		return Ok(());
+
+	}
+
+	fn draw(&self, gfx: &mut Gfx) -> Result<()> {
+
+		let (vw, vh) = self.view_size.unwrap_or((gfx.width() as f32, gfx.height() as f32));
+		let rows = self.rows_on_screen(vh);
+		let mut y = LINE_SPACING * 0.5;
+
+		for row in self.scroll..self.scroll + rows {
+
+			let offset = row * ROW_LEN;
+
+			if offset >= self.data.len() && offset > 0 {
+				break;
+			}
+
+			let bytes = &self.data[offset..(offset + ROW_LEN).min(self.data.len())];
+			let (text, hex_pos, ascii_pos) = format_row(offset, bytes);
+
+			let ftext = shapes::text(&text)
+				.align(gfx::Origin::TopLeft)
+				.size(FONT_SIZE)
+				.format(gfx);
+
+			// the byte under the cursor, in both columns
+			if self.cursor >= offset && self.cursor < offset + bytes.len() {
+
+				let i = self.cursor - offset;
+				let x = |c: usize| ftext.cursor_pos(c).map(|p| p.x).unwrap_or(0.0);
+				let color = if self.mode == Mode::Edit { rgba!(0.5, 1, 0.5, 0.5) } else { rgba!(1, 1, 1, 0.3) };
+
+				for (c, w) in &[(hex_pos[i], 2), (ascii_pos[i], 1)] {
+					gfx.draw(
+						&shapes::rect(
+							vec2!(x(*c), -y),
+							vec2!(x(c + w), -y - FONT_SIZE),
+						)
+							.fill(color)
+					)?;
+				}
+
+			}
+
+			gfx.draw_t(
+				mat4!()
+					.t2(vec2!(0, -y))
+					,
+				&ftext
+			)?;
+
+			y += LINE_HEIGHT;
+
+		}
+
+		let (m, c) = match self.mode {
+			Mode::Normal => ("hex", rgba!(0.5, 1, 1, 1)),
+			Mode::Edit => ("edit", rgba!(0.5, 1, 0.5, 1)),
+			Mode::Goto => ("goto offset", rgba!(1, 1, 0.5, 1)),
+			Mode::Search => ("search bytes", rgba!(1, 1, 0.5, 1)),
+		};
+
+		gfx.draw(
+			&shapes::rect(
+				vec2!(0, -vh + FONT_SIZE + LINE_SPACING * 2.0),
+				vec2!(vw, -vh),
+			)
+				.fill(c)
+		)?;
+
+		let label = match self.mode {
+			Mode::Goto | Mode::Search => format!("{}: {}", m.to_uppercase(), self.bar.content()),
+			_ => m.to_uppercase(),
+		};
+
+		gfx.draw_t(
+			mat4!()
+				.t2(vec2!(LINE_SPACING, -vh + LINE_SPACING))
+				,
+			&shapes::text(&label)
+				.align(Origin::BottomLeft)
+				.size(FONT_SIZE)
+				.color(rgba!(0, 0, 0, 1))
+		)?;
+
+		gfx.draw_t(
+			mat4!()
+				.t2(vec2!(vw - LINE_SPACING, -vh + LINE_SPACING))
+				,
+			&shapes::text(&format!("{:08x} / {:08x}", self.cursor, self.data.len()))
+				.align(Origin::BottomRight)
+				.size(FONT_SIZE)
+				.color(rgba!(0, 0, 0, 1))
+		)?;
+
+		return Ok(());
+
+	}
+
+}
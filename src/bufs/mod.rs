@@ -4,6 +4,8 @@ mod edit;
 pub use edit::*;
 mod img;
 pub use img::*;
+mod hex;
+pub use hex::*;
 mod model;
 pub use model::*;
 mod music;
@@ -1,5 +1,7 @@
 // wengwengweng
 
+use std::collections::HashMap;
+use std::path::PathBuf;
 use serde::Serialize;
 use serde::Deserialize;
 
@@ -12,15 +14,29 @@ pub struct Conf {
 	pub width: Option<i32>,
 	pub height: Option<i32>,
 	pub bookmarks: Option<Vec<String>>,
+	pub clipboard: Option<bool>,
+	// keep the last version of saved files in the data dir
+	pub backup: Option<bool>,
+	// editorconfig properties by language, e.g. [langs.Rust] indent_style = "space"
+	pub langs: Option<HashMap<String, HashMap<String, toml::Value>>>,
+	// language server command by language, e.g. [servers] Rust = "rust-analyzer"
+	pub servers: Option<HashMap<String, String>>,
+	// ms without typing before words and paths complete on their own, 0 only completes on alt+tab
+	pub complete_delay: Option<u64>,
 }
 
 impl Conf {
 
+	/// Where the conf is, other files that go with it are next to it.
+	pub fn path() -> Result<PathBuf> {
+		return dirs_next::home_dir()
+			.map(|home| home.join(FNAME))
+			.ok_or_else(|| format!("failed to get home dir"));
+	}
+
 	pub fn load() -> Result<Self> {
 
-		let home = dirs_next::home_dir()
-			.ok_or_else(|| format!("failed to get home dir"))?;
-		let path = home.join(FNAME);
+		let path = Self::path()?;
 		let content = std::fs::read_to_string(&path)
 			.map_err(|_| format!("failed to read {}", path.display()))?;
 
@@ -29,6 +45,25 @@ impl Conf {
 
 	}
 
+	/// Per-language properties as the strings an `.editorconfig` would have.
+	pub fn lang_props(&self) -> HashMap<String, HashMap<String, String>> {
+		return self.langs
+			.clone()
+			.unwrap_or_default()
+			.into_iter()
+			.map(|(lang, props)| {
+				let props = props
+					.into_iter()
+					.map(|(k, v)| {
+						let v = v.as_str().map(String::from).unwrap_or(v.to_string());
+						return (k, v);
+					})
+					.collect();
+				return (lang, props);
+			})
+			.collect();
+	}
+
 }
 
 impl Default for Conf {
@@ -37,6 +72,11 @@ impl Default for Conf {
 			width: None,
 			height: None,
 			bookmarks: None,
+			clipboard: None,
+			backup: None,
+			langs: None,
+			servers: None,
+			complete_delay: None,
 		};
 	}
 }
@@ -0,0 +1,1602 @@
+// wengwengweng
+
+use std::io::Read;
+use std::io::Write;
+use std::io::BufRead;
+use std::io::BufReader;
+use std::path::Path;
+use std::path::PathBuf;
+use std::process::Child;
+use std::sync::mpsc;
+use std::time::Instant;
+use std::time::Duration;
+use std::collections::HashMap;
+use std::collections::HashSet;
+
+use serde_json::Value;
+use serde_json::json;
+
+use crate::*;
+
+// how long a server gets to answer shutdown and then exit, before it's killed
+const SHUTDOWN_TIMEOUT: Duration = Duration::from_millis(500);
+
+/// Read a message framed by a Content-Length header, `None` when the stream ended.
+pub fn read_msg(r: &mut impl BufRead) -> Result<Option<Value>> {
+
+	let mut len = None;
+
+	loop {
+
+		let mut line = String::new();
+
+		let n = r
+			.read_line(&mut line)
+			.map_err(|_| format!("failed to read message"))?;
+
+		if n == 0 {
+			return Ok(None);
+		}
+
+		let line = line.trim_end();
+
+		if line.is_empty() {
+			break;
+		}
+
+		if let Some(v) = line.strip_prefix("Content-Length:") {
+			len = v.trim().parse::<usize>().ok();
+		}
+
+	}
+
+	let len = len.ok_or_else(|| format!("message without length"))?;
+	let mut buf = vec![0; len];
+
+	r.read_exact(&mut buf)
+		.map_err(|_| format!("failed to read message"))?;
+
+	return serde_json::from_slice(&buf)
+		.map(Some)
+		.map_err(|_| format!("invalid message"));
+
+}
+
+/// Write a message with a Content-Length header.
+pub fn write_msg<W: Write + ?Sized>(w: &mut W, msg: &Value) -> Result<()> {
+
+	let body = msg.to_string();
+
+	write!(w, "Content-Length: {}\r\n\r\n{}", body.len(), body)
+		.and_then(|_| w.flush())
+		.map_err(|_| format!("failed to write message"))?;
+
+	return Ok(());
+
+}
+
+pub fn path_to_uri(path: impl AsRef<Path>) -> String {
+
+	let path = path.as_ref();
+	let path = path.canonicalize().unwrap_or(path.to_path_buf());
+	let mut uri = String::from("file://");
+
+	for b in path.to_string_lossy().bytes() {
+		match b {
+			b'A'..=b'Z' | b'a'..=b'z' | b'0'..=b'9' | b'/' | b'-' | b'_' | b'.' | b'~' => uri.push(b as char),
+			_ => uri.push_str(&format!("%{:02X}", b)),
+		}
+	}
+
+	return uri;
+
+}
+
+pub fn uri_to_path(uri: &str) -> Option<PathBuf> {
+
+	let s = uri.strip_prefix("file://")?.as_bytes();
+	let mut bytes = vec![];
+	let mut i = 0;
+
+	while i < s.len() {
+		if s[i] == b'%' && i + 2 < s.len() {
+			let hex = std::str::from_utf8(&s[i + 1..i + 3]).ok()?;
+			bytes.push(u8::from_str_radix(hex, 16).ok()?);
+			i += 3;
+		} else {
+			bytes.push(s[i]);
+			i += 1;
+		}
+	}
+
+	return String::from_utf8(bytes).ok().map(PathBuf::from);
+
+}
+
+// a file can come in other uris than the one we made for it, like encoded differently or through a symlink, so they're all keyed the same way
+fn normalize_uri(uri: &str) -> String {
+	return uri_to_path(uri)
+		.map(path_to_uri)
+		.unwrap_or(uri.to_string());
+}
+
+// the closest dir up with version control, or the file's dir
+fn find_root(path: &Path) -> PathBuf {
+
+	let dir = path.parent().unwrap_or(path);
+
+	return dir
+		.ancestors()
+		.find(|d| d.join(".git").exists())
+		.unwrap_or(dir)
+		.to_path_buf();
+
+}
+
+/// Language id servers know a syntax by.
+fn lang_id(lang: &str) -> String {
+	return match lang {
+		"C++" => "cpp".to_string(),
+		"JavaScript" => "javascript".to_string(),
+		"Makefile" => "makefile".to_string(),
+		_ => lang.to_lowercase(),
+	};
+}
+
+/// Position as servers count it, utf-16 code units into the line.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct Pos {
+	pub line: usize,
+	pub character: usize,
+}
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct Range {
+	pub start: Pos,
+	pub end: Pos,
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq, PartialOrd, Ord)]
+pub enum Severity {
+	Error,
+	Warning,
+	Info,
+	Hint,
+}
+
+#[derive(Clone, Debug, PartialEq)]
+pub struct Diagnostic {
+	pub range: Range,
+	pub severity: Severity,
+	pub message: String,
+}
+
+#[derive(Clone, Debug, PartialEq)]
+pub struct Location {
+	pub path: PathBuf,
+	pub range: Range,
+}
+
+#[derive(Clone, Debug, PartialEq)]
+pub struct TextEdit {
+	pub range: Range,
+	pub text: String,
+}
+
+#[derive(Clone, Debug, PartialEq)]
+pub struct CompletionItem {
+	pub label: String,
+	pub detail: Option<String>,
+	pub insert: String,
+	pub range: Option<Range>,
+}
+
+/// A change to a document, a range of it as it was before or all of it.
+#[derive(Clone, Debug, PartialEq)]
+pub struct ContentChange {
+	pub range: Option<Range>,
+	pub text: String,
+}
+
+/// Something a server sent for a document.
+#[derive(Clone, Debug, PartialEq)]
+pub enum LspEvent {
+	Diagnostics(Vec<Diagnostic>),
+	Completion(Vec<CompletionItem>),
+	Hover(String),
+	Definition(Vec<Location>),
+	References(Vec<Location>),
+	Edits(Vec<TextEdit>),
+	// other files a rename changed
+	Renamed(Vec<PathBuf>),
+	// jump there once the document is open, after going to a definition in another file
+	Goto(Pos),
+}
+
+/// What a request was for, to know what to make of the response.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum Req {
+	Initialize,
+	Completion,
+	Hover,
+	Definition,
+	References,
+	Rename,
+}
+
+impl Req {
+	fn method(&self) -> &'static str {
+		return match self {
+			Req::Initialize => "initialize",
+			Req::Completion => "textDocument/completion",
+			Req::Hover => "textDocument/hover",
+			Req::Definition => "textDocument/definition",
+			Req::References => "textDocument/references",
+			Req::Rename => "textDocument/rename",
+		};
+	}
+}
+
+/// A running server, messages are held back until it's initialized.
+///
+/// Messages are written from a thread, so a server that's slow to read doesn't hold anything up.
+pub struct Server {
+	child: Option<Child>,
+	writer: mpsc::Sender<Value>,
+	rx: mpsc::Receiver<Value>,
+	next_id: u64,
+	// requests waiting for a response, and the document they're for
+	requests: HashMap<u64, (String, Req)>,
+	ready: bool,
+	queue: Vec<Value>,
+	caps: Value,
+}
+
+impl Server {
+
+	/// Start a server from a command line, the documents are under `root`.
+	pub fn spawn(cmd: &str, root: impl AsRef<Path>) -> Result<Self> {
+
+		let mut args = cmd.split_whitespace();
+		let prog = args.next().ok_or_else(|| format!("empty server command"))?;
+
+		let mut child = std::process::Command::new(prog)
+			.args(args)
+			.current_dir(root.as_ref())
+			.stdin(std::process::Stdio::piped())
+			.stdout(std::process::Stdio::piped())
+			.stderr(std::process::Stdio::null())
+			.spawn()
+			.map_err(|_| format!("failed to start '{}'", cmd))?;
+
+		let stdin = child.stdin.take().ok_or_else(|| format!("failed to start '{}'", cmd))?;
+		let stdout = child.stdout.take().ok_or_else(|| format!("failed to start '{}'", cmd))?;
+		let mut server = Self::from_io(stdout, stdin, root)?;
+
+		server.child = Some(child);
+
+		return Ok(server);
+
+	}
+
+	/// A server on any pair of streams, starts by sending `initialize`.
+	pub fn from_io(reader: impl Read + Send + 'static, mut writer: impl Write + Send + 'static, root: impl AsRef<Path>) -> Result<Self> {
+
+		let (tx, rx) = mpsc::channel();
+		let (wtx, wrx) = mpsc::channel::<Value>();
+
+		std::thread::spawn(move || {
+			for msg in wrx {
+				if write_msg(&mut writer, &msg).is_err() {
+					break;
+				}
+			}
+		});
+
+		std::thread::spawn(move || {
+			let mut r = BufReader::new(reader);
+			while let Ok(Some(msg)) = read_msg(&mut r) {
+				if tx.send(msg).is_err() {
+					break;
+				}
+			}
+		});
+
+		let mut server = Self {
+			child: None,
+			writer: wtx,
+			rx: rx,
+			next_id: 0,
+			requests: hmap![],
+			ready: false,
+			queue: vec![],
+			caps: Value::Null,
+		};
+
+		let root = root.as_ref();
+		let root_uri = path_to_uri(root);
+
+		server.request(String::new(), Req::Initialize, json!({
+			"processId": std::process::id(),
+			"rootUri": root_uri,
+			"rootPath": root.display().to_string(),
+			"workspaceFolders": [{
+				"uri": root_uri,
+				"name": root.file_name().map(|n| n.to_string_lossy().to_string()).unwrap_or(String::new()),
+			}],
+			"capabilities": {
+				"textDocument": {
+					"synchronization": {
+						"didSave": true,
+					},
+					"completion": {
+						"completionItem": {
+							"snippetSupport": false,
+						},
+					},
+					"hover": {
+						"contentFormat": ["plaintext", "markdown"],
+					},
+					"definition": {
+						"linkSupport": true,
+					},
+					"references": {},
+					"rename": {},
+					"publishDiagnostics": {},
+				},
+				"workspace": {
+					"workspaceEdit": {
+						"documentChanges": true,
+					},
+					"configuration": true,
+				},
+			},
+		}))?;
+
+		return Ok(server);
+
+	}
+
+	fn send(&mut self, msg: Value) -> Result<()> {
+
+		if !self.ready && msg["method"] != "initialize" {
+			self.queue.push(msg);
+			return Ok(());
+		}
+
+		return self.write(msg);
+
+	}
+
+	// the writer thread stops when the server's gone
+	fn write(&mut self, msg: Value) -> Result<()> {
+		return self.writer
+			.send(msg)
+			.map_err(|_| format!("failed to write message"));
+	}
+
+	fn notify(&mut self, method: &str, params: Value) -> Result<()> {
+		return self.send(json!({
+			"jsonrpc": "2.0",
+			"method": method,
+			"params": params,
+		}));
+	}
+
+	fn request(&mut self, uri: String, req: Req, params: Value) -> Result<()> {
+
+		let id = self.next_id;
+
+		self.next_id += 1;
+		self.requests.insert(id, (uri, req));
+
+		return self.send(json!({
+			"jsonrpc": "2.0",
+			"id": id,
+			"method": req.method(),
+			"params": params,
+		}));
+
+	}
+
+	fn respond(&mut self, id: Value, result: Value) -> Result<()> {
+		return self.write(json!({
+			"jsonrpc": "2.0",
+			"id": id,
+			"result": result,
+		}));
+	}
+
+	// the server said it's ready, send what was held back
+	fn initialized(&mut self, caps: Value) -> Result<()> {
+
+		self.caps = caps;
+		self.ready = true;
+		self.notify("initialized", json!({}))?;
+
+		for msg in std::mem::replace(&mut self.queue, vec![]) {
+			self.write(msg)?;
+		}
+
+		return Ok(());
+
+	}
+
+	// 2 is ranges, 1 the whole text and 0 nothing, which gets the whole text anyway
+	fn incremental(&self) -> bool {
+		let sync = &self.caps["textDocumentSync"];
+		return sync.as_u64().or(sync["change"].as_u64()) == Some(2);
+	}
+
+	fn trigger_chars(&self) -> Vec<char> {
+		return self.caps["completionProvider"]["triggerCharacters"]
+			.as_array()
+			.map(|a| {
+				return a
+					.iter()
+					.filter_map(|c| c.as_str()?.chars().next())
+					.collect();
+			})
+			.unwrap_or(vec![]);
+	}
+
+}
+
+impl Drop for Server {
+
+	// shutdown, then exit once it's answered, a server that's not initialized only takes exit
+	fn drop(&mut self) {
+
+		let deadline = Instant::now() + SHUTDOWN_TIMEOUT;
+
+		if self.ready {
+
+			let id = self.next_id;
+
+			self.write(json!({
+				"jsonrpc": "2.0",
+				"id": id,
+				"method": "shutdown",
+			})).ok();
+
+			while let Ok(msg) = self.rx.recv_timeout(deadline.saturating_duration_since(Instant::now())) {
+				if msg["id"] == id && msg.get("method").is_none() {
+					break;
+				}
+			}
+
+		}
+
+		self.write(json!({
+			"jsonrpc": "2.0",
+			"method": "exit",
+		})).ok();
+
+		if let Some(child) = &mut self.child {
+			while Instant::now() < deadline {
+				if let Ok(Some(_)) = child.try_wait() {
+					return;
+				}
+				std::thread::sleep(Duration::from_millis(10));
+			}
+			child.kill().ok();
+			child.wait().ok();
+		}
+
+	}
+
+}
+
+fn parse_pos(v: &Value) -> Option<Pos> {
+	return Some(Pos {
+		line: v["line"].as_u64()? as usize,
+		character: v["character"].as_u64()? as usize,
+	});
+}
+
+fn parse_range(v: &Value) -> Option<Range> {
+	return Some(Range {
+		start: parse_pos(&v["start"])?,
+		end: parse_pos(&v["end"])?,
+	});
+}
+
+// a Location or a LocationLink
+fn parse_location(v: &Value) -> Option<Location> {
+
+	let uri = v["uri"].as_str().or(v["targetUri"].as_str())?;
+	let range = parse_range(&v["range"]).or(parse_range(&v["targetSelectionRange"]))?;
+
+	return Some(Location {
+		path: uri_to_path(uri)?,
+		range: range,
+	});
+
+}
+
+fn parse_locations(v: &Value) -> Vec<Location> {
+	return match v {
+		Value::Array(a) => a.iter().filter_map(parse_location).collect(),
+		Value::Null => vec![],
+		v => parse_location(v).into_iter().collect(),
+	};
+}
+
+fn parse_edits(v: &Value) -> Vec<TextEdit> {
+	return v
+		.as_array()
+		.map(|a| {
+			return a
+				.iter()
+				.filter_map(|e| {
+					return Some(TextEdit {
+						range: parse_range(&e["range"])?,
+						text: e["newText"].as_str()?.to_string(),
+					});
+				})
+				.collect();
+		})
+		.unwrap_or(vec![]);
+}
+
+// edits by document, from "changes" or "documentChanges"
+fn parse_workspace_edit(v: &Value) -> Vec<(String, Vec<TextEdit>)> {
+
+	let mut out = vec![];
+
+	if let Some(changes) = v["changes"].as_object() {
+		for (uri, edits) in changes {
+			out.push((uri.clone(), parse_edits(edits)));
+		}
+	}
+
+	if let Some(changes) = v["documentChanges"].as_array() {
+		for c in changes {
+			if let Some(uri) = c["textDocument"]["uri"].as_str() {
+				out.push((uri.to_string(), parse_edits(&c["edits"])));
+			}
+		}
+	}
+
+	return out;
+
+}
+
+// we don't do snippets, so keep the placeholder text and drop the tab stops
+fn strip_snippet(s: &str) -> String {
+
+	static PLACEHOLDER: once_cell::sync::Lazy<regex::Regex> = once_cell::sync::Lazy::new(|| {
+		return regex::Regex::new(r"\$\{\d+:([^}]*)\}").unwrap();
+	});
+
+	static TABSTOP: once_cell::sync::Lazy<regex::Regex> = once_cell::sync::Lazy::new(|| {
+		return regex::Regex::new(r"\$\{\d+\}|\$\d+").unwrap();
+	});
+
+	let s = PLACEHOLDER.replace_all(s, "$1");
+
+	return TABSTOP.replace_all(&s, "").to_string();
+
+}
+
+fn parse_completion(v: &Value) -> Vec<CompletionItem> {
+
+	let items = match v {
+		Value::Array(a) => a,
+		v => match v["items"].as_array() {
+			Some(a) => a,
+			None => return vec![],
+		},
+	};
+
+	return items
+		.iter()
+		.filter_map(|i| {
+
+			let label = i["label"].as_str()?.to_string();
+			let edit = &i["textEdit"];
+			let insert = edit["newText"]
+				.as_str()
+				.or(i["insertText"].as_str())
+				.unwrap_or(&label)
+				.to_string();
+
+			// 2 is snippet
+			let insert = if i["insertTextFormat"] == 2 {
+				strip_snippet(&insert)
+			} else {
+				insert
+			};
+
+			return Some(CompletionItem {
+				label: label,
+				detail: i["detail"].as_str().map(String::from),
+				insert: insert,
+				range: parse_range(&edit["range"]).or(parse_range(&edit["insert"])),
+			});
+
+		})
+		.collect();
+
+}
+
+fn hover_text(v: &Value) -> String {
+	return match v {
+		Value::String(s) => s.clone(),
+		Value::Array(a) => a
+			.iter()
+			.map(hover_text)
+			.filter(|s| !s.is_empty())
+			.collect::<Vec<String>>()
+			.join("\n\n"),
+		Value::Object(_) => v["value"].as_str().unwrap_or("").to_string(),
+		_ => String::new(),
+	};
+}
+
+fn parse_diagnostics(v: &Value) -> Vec<Diagnostic> {
+	return v
+		.as_array()
+		.map(|a| {
+			return a
+				.iter()
+				.filter_map(|d| {
+					return Some(Diagnostic {
+						range: parse_range(&d["range"])?,
+						severity: match d["severity"].as_u64() {
+							Some(2) => Severity::Warning,
+							Some(3) => Severity::Info,
+							Some(4) => Severity::Hint,
+							_ => Severity::Error,
+						},
+						message: d["message"].as_str()?.to_string(),
+					});
+				})
+				.collect();
+		})
+		.unwrap_or(vec![]);
+}
+
+/// Servers for each language, and what they sent waiting to be picked up by documents.
+pub struct Lsp {
+	// command line of the server for each syntax name
+	cmds: HashMap<String, String>,
+	servers: HashMap<String, Server>,
+	// languages whose server failed, so it isn't started again for every file
+	failed: HashSet<String>,
+	events: HashMap<String, Vec<LspEvent>>,
+	// files with edits from a rename waiting, to go in right away whether they're open or not
+	edited: Vec<PathBuf>,
+	log: Vec<Msg>,
+}
+
+impl Lsp {
+
+	pub fn new(cmds: HashMap<String, String>) -> Self {
+		return Self {
+			cmds: cmds,
+			servers: hmap![],
+			failed: hset![],
+			events: hmap![],
+			edited: vec![],
+			log: vec![],
+		};
+	}
+
+	fn server(&mut self, lang: &str, path: &Path) -> Option<&mut Server> {
+
+		if !self.servers.contains_key(lang) && !self.failed.contains(lang) {
+
+			let cmd = self.cmds.get(lang)?;
+
+			match Server::spawn(cmd, find_root(path)) {
+				Ok(s) => {
+					self.servers.insert(lang.to_string(), s);
+				},
+				Err(e) => {
+					self.failed.insert(lang.to_string());
+					self.log.push(Msg::error(&e));
+				},
+			}
+
+		}
+
+		return self.servers.get_mut(lang);
+
+	}
+
+	// a failed write means the server is gone
+	fn check(&mut self, lang: &str, res: Result<()>) {
+		if let Err(e) = res {
+			self.servers.remove(lang);
+			self.failed.insert(lang.to_string());
+			self.log.push(Msg::error(&format!("{} server: {}", lang, e)));
+		}
+	}
+
+	pub fn open(&mut self, lang: &str, path: &Path, text: &str) {
+
+		let uri = path_to_uri(path);
+
+		let res = match self.server(lang, path) {
+			Some(s) => s.notify("textDocument/didOpen", json!({
+				"textDocument": {
+					"uri": uri,
+					"languageId": lang_id(lang),
+					"version": 0,
+					"text": text,
+				},
+			})),
+			None => return,
+		};
+
+		self.check(lang, res);
+
+	}
+
+	/// Edits to a document, in the order they were made.
+	pub fn change(&mut self, lang: &str, path: &Path, version: i64, changes: Vec<ContentChange>) {
+
+		let uri = path_to_uri(path);
+		let changes = changes
+			.into_iter()
+			.map(|c| {
+				return match c.range {
+					Some(r) => json!({
+						"range": {
+							"start": {
+								"line": r.start.line,
+								"character": r.start.character,
+							},
+							"end": {
+								"line": r.end.line,
+								"character": r.end.character,
+							},
+						},
+						"text": c.text,
+					}),
+					None => json!({
+						"text": c.text,
+					}),
+				};
+			})
+			.collect::<Vec<Value>>();
+
+		let res = match self.servers.get_mut(lang) {
+			Some(s) => s.notify("textDocument/didChange", json!({
+				"textDocument": {
+					"uri": uri,
+					"version": version,
+				},
+				"contentChanges": changes,
+			})),
+			None => return,
+		};
+
+		self.check(lang, res);
+
+	}
+
+	pub fn save(&mut self, lang: &str, path: &Path) {
+
+		let uri = path_to_uri(path);
+
+		let res = match self.servers.get_mut(lang) {
+			Some(s) => s.notify("textDocument/didSave", json!({
+				"textDocument": {
+					"uri": uri,
+				},
+			})),
+			None => return,
+		};
+
+		self.check(lang, res);
+
+	}
+
+	pub fn close(&mut self, lang: &str, path: &Path) {
+
+		let uri = path_to_uri(path);
+
+		let res = match self.servers.get_mut(lang) {
+			Some(s) => s.notify("textDocument/didClose", json!({
+				"textDocument": {
+					"uri": uri,
+				},
+			})),
+			None => return,
+		};
+
+		self.events.remove(&uri);
+		self.check(lang, res);
+
+	}
+
+	/// Ask about a position in a document, the response comes as an event for it.
+	pub fn request(&mut self, lang: &str, path: &Path, req: Req, pos: Pos, extra: Value) -> Result<()> {
+
+		let uri = path_to_uri(path);
+
+		let mut params = json!({
+			"textDocument": {
+				"uri": uri,
+			},
+			"position": {
+				"line": pos.line,
+				"character": pos.character,
+			},
+		});
+
+		if let (Some(p), Some(extra)) = (params.as_object_mut(), extra.as_object()) {
+			p.extend(extra.clone());
+		}
+
+		let res = match self.servers.get_mut(lang) {
+			Some(s) => s.request(uri, req, params),
+			None => return Err(format!("no language server for {}", lang)),
+		};
+
+		self.check(lang, res);
+
+		return Ok(());
+
+	}
+
+	/// If a server for the language is running.
+	pub fn has_server(&self, lang: &str) -> bool {
+		return self.servers.contains_key(lang);
+	}
+
+	/// If the server takes changes as ranges, not the whole text.
+	pub fn incremental(&self, lang: &str) -> bool {
+		return self.servers
+			.get(lang)
+			.map(|s| s.incremental())
+			.unwrap_or(false);
+	}
+
+	pub fn trigger_chars(&self, lang: &str) -> Vec<char> {
+		return self.servers
+			.get(lang)
+			.map(|s| s.trigger_chars())
+			.unwrap_or(vec![]);
+	}
+
+	/// Handle everything servers sent since last time.
+	pub fn poll(&mut self) {
+
+		let mut msgs = vec![];
+		let mut gone = vec![];
+
+		for (lang, s) in &self.servers {
+			loop {
+				match s.rx.try_recv() {
+					Ok(msg) => msgs.push((lang.clone(), msg)),
+					Err(mpsc::TryRecvError::Empty) => break,
+					Err(mpsc::TryRecvError::Disconnected) => {
+						gone.push(lang.clone());
+						break;
+					},
+				}
+			}
+		}
+
+		for (lang, msg) in msgs {
+			let res = self.handle(&lang, msg);
+			self.check(&lang, res);
+		}
+
+		for lang in gone {
+			if self.servers.remove(&lang).is_some() {
+				self.failed.insert(lang.clone());
+				self.log.push(Msg::error(&format!("{} server exited", lang)));
+			}
+		}
+
+	}
+
+	fn push_event(&mut self, uri: &str, e: LspEvent) {
+		self.events
+			.entry(uri.to_string())
+			.or_insert(vec![])
+			.push(e);
+	}
+
+	/// Leave something for a document to pick up when it's opened.
+	pub fn push_event_for(&mut self, path: &Path, e: LspEvent) {
+		self.push_event(&path_to_uri(path), e);
+	}
+
+	pub fn take_events(&mut self, path: &Path) -> Vec<LspEvent> {
+		return self.events
+			.remove(&path_to_uri(path))
+			.unwrap_or(vec![]);
+	}
+
+	/// Files a rename left edits for since last time.
+	pub fn take_edited(&mut self) -> Vec<PathBuf> {
+		return std::mem::take(&mut self.edited);
+	}
+
+	pub fn log(&mut self) -> &mut Vec<Msg> {
+		return &mut self.log;
+	}
+
+	fn handle(&mut self, lang: &str, msg: Value) -> Result<()> {
+
+		let method = msg["method"].as_str().map(String::from);
+
+		match (method, msg.get("id").cloned()) {
+
+			// a request from the server, nothing we do needs answers other than nothing
+			(Some(method), Some(id)) => {
+
+				let result = if method == "workspace/configuration" {
+					let n = msg["params"]["items"].as_array().map(|a| a.len()).unwrap_or(0);
+					Value::Array(vec![Value::Null; n])
+				} else {
+					Value::Null
+				};
+
+				if let Some(s) = self.servers.get_mut(lang) {
+					s.respond(id, result)?;
+				}
+
+			},
+
+			(Some(method), None) => {
+
+				let params = &msg["params"];
+
+				match method.as_str() {
+					"textDocument/publishDiagnostics" => {
+						if let Some(uri) = params["uri"].as_str() {
+							let uri = normalize_uri(uri);
+							let diags = parse_diagnostics(&params["diagnostics"]);
+							// only the latest matters
+							if let Some(events) = self.events.get_mut(&uri) {
+								events.retain(|e| !matches!(e, LspEvent::Diagnostics(_)));
+							}
+							self.push_event(&uri, LspEvent::Diagnostics(diags));
+						}
+					},
+					"window/showMessage" => {
+						let text = params["message"].as_str().unwrap_or("");
+						let msg = match params["type"].as_u64() {
+							Some(1) => Msg::error(text),
+							_ => Msg::info(text),
+						};
+						self.log.push(msg);
+					},
+					_ => {},
+				}
+
+			},
+
+			(None, Some(id)) => {
+
+				let (uri, req) = match id.as_u64().and_then(|id| self.servers.get_mut(lang)?.requests.remove(&id)) {
+					Some(r) => r,
+					None => return Ok(()),
+				};
+
+				if let Some(err) = msg.get("error") {
+					let text = err["message"].as_str().unwrap_or("request failed");
+					// it'll never be ready, the server's dropped with what's held back for it
+					if req == Req::Initialize {
+						return Err(format!("failed to initialize: {}", text));
+					}
+					// -32800 is cancelled, which isn't worth telling
+					if err["code"] != -32800 {
+						self.log.push(Msg::error(&format!("{}: {}", lang, text)));
+					}
+					return Ok(());
+				}
+
+				let result = &msg["result"];
+
+				match req {
+					Req::Initialize => {
+						if let Some(s) = self.servers.get_mut(lang) {
+							s.initialized(result["capabilities"].clone())?;
+						}
+					},
+					Req::Completion => self.push_event(&uri, LspEvent::Completion(parse_completion(result))),
+					Req::Hover => self.push_event(&uri, LspEvent::Hover(hover_text(&result["contents"]))),
+					Req::Definition => self.push_event(&uri, LspEvent::Definition(parse_locations(result))),
+					Req::References => self.push_event(&uri, LspEvent::References(parse_locations(result))),
+					Req::Rename => {
+
+						let changes = parse_workspace_edit(result);
+						let mut others = vec![];
+
+						for (target, edits) in changes {
+							let target = normalize_uri(&target);
+							if let Some(path) = uri_to_path(&target) {
+								if target != uri {
+									others.push(path.clone());
+								}
+								self.edited.push(path);
+							}
+							self.push_event(&target, LspEvent::Edits(edits));
+						}
+
+						self.push_event(&uri, LspEvent::Renamed(others));
+
+					},
+				}
+
+			},
+
+			_ => {},
+
+		}
+
+		return Ok(());
+
+	}
+
+}
+
+#[cfg(test)]
+mod tests {
+
+	use super::*;
+	use std::io::ErrorKind;
+	use std::time::Duration;
+
+	// one way in-memory stream, reads give up after a while so a test that's waiting for nothing fails instead of hanging
+	struct PipeReader {
+		rx: mpsc::Receiver<Vec<u8>>,
+		buf: Vec<u8>,
+	}
+
+	struct PipeWriter(mpsc::Sender<Vec<u8>>);
+
+	fn pipe() -> (PipeReader, PipeWriter) {
+		let (tx, rx) = mpsc::channel();
+		return (PipeReader {
+			rx: rx,
+			buf: vec![],
+		}, PipeWriter(tx));
+	}
+
+	impl PipeReader {
+		// if nothing's been written since the last read, after giving the writer thread a moment
+		fn is_empty(&mut self) -> bool {
+			if self.buf.is_empty() {
+				if let Ok(b) = self.rx.recv_timeout(Duration::from_millis(100)) {
+					self.buf = b;
+				}
+			}
+			return self.buf.is_empty();
+		}
+	}
+
+	impl Read for PipeReader {
+		fn read(&mut self, out: &mut [u8]) -> std::io::Result<usize> {
+			let n = {
+				let buf = self.fill_buf()?;
+				let n = out.len().min(buf.len());
+				out[..n].copy_from_slice(&buf[..n]);
+				n
+			};
+			self.consume(n);
+			return Ok(n);
+		}
+	}
+
+	impl BufRead for PipeReader {
+		fn fill_buf(&mut self) -> std::io::Result<&[u8]> {
+			if self.buf.is_empty() {
+				match self.rx.recv_timeout(Duration::from_secs(2)) {
+					Ok(b) => self.buf = b,
+					Err(mpsc::RecvTimeoutError::Timeout) => return Err(ErrorKind::TimedOut.into()),
+					Err(mpsc::RecvTimeoutError::Disconnected) => {},
+				}
+			}
+			return Ok(&self.buf);
+		}
+		fn consume(&mut self, n: usize) {
+			self.buf.drain(..n);
+		}
+	}
+
+	impl Write for PipeWriter {
+		fn write(&mut self, buf: &[u8]) -> std::io::Result<usize> {
+			self.0
+				.send(buf.to_vec())
+				.map_err(|_| std::io::Error::from(ErrorKind::BrokenPipe))?;
+			return Ok(buf.len());
+		}
+		fn flush(&mut self) -> std::io::Result<()> {
+			return Ok(());
+		}
+	}
+
+	// the other end of a server connection, what the client sent and where to answer
+	struct Remote {
+		r: PipeReader,
+		w: PipeWriter,
+	}
+
+	impl Remote {
+
+		fn recv(&mut self) -> Value {
+			return read_msg(&mut self.r)
+				.expect("failed to read")
+				.expect("stream ended");
+		}
+
+		fn send(&mut self, msg: Value) {
+			write_msg(&mut self.w, &msg).unwrap();
+		}
+
+		fn respond(&mut self, req: &Value, result: Value) {
+			self.send(json!({
+				"jsonrpc": "2.0",
+				"id": req["id"].clone(),
+				"result": result,
+			}));
+		}
+
+		// take the initialize request and answer it
+		fn initialize(&mut self, caps: Value) {
+			let init = self.recv();
+			assert_eq!(init["method"], "initialize");
+			self.respond(&init, json!({
+				"capabilities": caps,
+			}));
+		}
+
+	}
+
+	const LANG: &str = "Rust";
+
+	// a file that exists, so its uri is the same however it's made
+	fn test_file(name: &str) -> PathBuf {
+		let dir = std::env::temp_dir().join(format!("space55-lsp-test-{}", std::process::id()));
+		std::fs::create_dir_all(&dir).unwrap();
+		let path = dir.join(name);
+		std::fs::write(&path, "").unwrap();
+		return path.canonicalize().unwrap();
+	}
+
+	fn connect() -> (Lsp, Remote) {
+
+		let (client_r, remote_w) = pipe();
+		let (remote_r, client_w) = pipe();
+		let server = Server::from_io(client_r, client_w, std::env::temp_dir()).unwrap();
+		let mut lsp = Lsp::new(hmap![]);
+
+		lsp.servers.insert(LANG.to_string(), server);
+
+		return (lsp, Remote {
+			r: remote_r,
+			w: remote_w,
+		});
+
+	}
+
+	// poll until there's something for the file
+	fn wait_events(lsp: &mut Lsp, path: &Path) -> Vec<LspEvent> {
+		for _ in 0..100 {
+			lsp.poll();
+			let events = lsp.take_events(path);
+			if !events.is_empty() {
+				return events;
+			}
+			std::thread::sleep(Duration::from_millis(10));
+		}
+		panic!("no events for {}", path.display());
+	}
+
+	fn wait_initialized(lsp: &mut Lsp) {
+		for _ in 0..100 {
+			lsp.poll();
+			if lsp.servers[LANG].ready {
+				return;
+			}
+			std::thread::sleep(Duration::from_millis(10));
+		}
+		panic!("never initialized");
+	}
+
+	fn range(l1: usize, c1: usize, l2: usize, c2: usize) -> Range {
+		return Range {
+			start: Pos {
+				line: l1,
+				character: c1,
+			},
+			end: Pos {
+				line: l2,
+				character: c2,
+			},
+		};
+	}
+
+	#[test]
+	fn msg_round_trip() {
+
+		let msg = json!({
+			"jsonrpc": "2.0",
+			"method": "textDocument/didOpen",
+			"params": {
+				"text": "héllo\r\n",
+			},
+		});
+
+		let mut buf = vec![];
+
+		write_msg(&mut buf, &msg).unwrap();
+		write_msg(&mut buf, &msg).unwrap();
+
+		let body = msg.to_string();
+
+		assert!(buf.starts_with(format!("Content-Length: {}\r\n\r\n", body.len()).as_bytes()));
+
+		let mut r = &buf[..];
+
+		assert_eq!(read_msg(&mut r), Ok(Some(msg.clone())));
+		assert_eq!(read_msg(&mut r), Ok(Some(msg)));
+		assert_eq!(read_msg(&mut r), Ok(None));
+
+	}
+
+	#[test]
+	fn read_msg_headers() {
+
+		let mut r = &b"Content-Type: application/vscode-jsonrpc; charset=utf-8\r\nContent-Length: 2\r\n\r\n{}"[..];
+		assert_eq!(read_msg(&mut r), Ok(Some(json!({}))));
+
+		let mut r = &b"Content-Type: x\r\n\r\n{}"[..];
+		assert!(read_msg(&mut r).is_err());
+
+		let mut r = &b"Content-Length: 2\r\n\r\n{"[..];
+		assert!(read_msg(&mut r).is_err());
+
+	}
+
+	#[test]
+	fn uri_paths() {
+
+		assert_eq!(uri_to_path("file:///a%20b/c%2Bd.rs"), Some(PathBuf::from("/a b/c+d.rs")));
+		assert_eq!(uri_to_path("file:///a%c3%a9.rs"), Some(PathBuf::from("/aé.rs")));
+		assert_eq!(uri_to_path("file:///a+b.rs"), Some(PathBuf::from("/a+b.rs")));
+		assert_eq!(uri_to_path("file:///a%2"), Some(PathBuf::from("/a%2")));
+		assert_eq!(uri_to_path("https://a.rs"), None);
+		assert_eq!(uri_to_path("file:///a%zz"), None);
+
+		let path = test_file("a b+c.rs");
+		let uri = path_to_uri(&path);
+
+		assert!(uri.ends_with("/a%20b%2Bc.rs"));
+		assert_eq!(uri_to_path(&uri), Some(path.clone()));
+		assert_eq!(normalize_uri(&uri.replace("%2B", "+")), uri);
+
+	}
+
+	#[test]
+	fn held_until_initialized() {
+
+		let (mut lsp, mut remote) = connect();
+		let path = test_file("init.rs");
+
+		lsp.open(LANG, &path, "fn main() {}");
+
+		let init = remote.recv();
+
+		assert_eq!(init["method"], "initialize");
+		assert!(remote.r.is_empty());
+
+		remote.respond(&init, json!({
+			"capabilities": {
+				"textDocumentSync": {
+					"change": 2,
+				},
+			},
+		}));
+
+		wait_initialized(&mut lsp);
+
+		assert_eq!(remote.recv()["method"], "initialized");
+
+		let open = remote.recv();
+
+		assert_eq!(open["method"], "textDocument/didOpen");
+		assert_eq!(open["params"]["textDocument"]["uri"], path_to_uri(&path));
+		assert_eq!(open["params"]["textDocument"]["text"], "fn main() {}");
+		assert!(lsp.incremental(LANG));
+
+		lsp.change(LANG, &path, 1, vec![ContentChange {
+			range: Some(range(0, 11, 0, 11)),
+			text: String::from(" "),
+		}]);
+
+		let change = remote.recv();
+
+		assert_eq!(change["method"], "textDocument/didChange");
+		assert_eq!(change["params"]["contentChanges"], json!([{
+			"range": {
+				"start": {
+					"line": 0,
+					"character": 11,
+				},
+				"end": {
+					"line": 0,
+					"character": 11,
+				},
+			},
+			"text": " ",
+		}]));
+
+	}
+
+	#[test]
+	fn initialize_error() {
+
+		let (mut lsp, mut remote) = connect();
+		let path = test_file("init error.rs");
+
+		lsp.open(LANG, &path, "");
+
+		let init = remote.recv();
+
+		remote.send(json!({
+			"jsonrpc": "2.0",
+			"id": init["id"].clone(),
+			"error": {
+				"code": -32603,
+				"message": "no",
+			},
+		}));
+
+		for _ in 0..100 {
+			lsp.poll();
+			if !lsp.servers.contains_key(LANG) {
+				break;
+			}
+			std::thread::sleep(Duration::from_millis(10));
+		}
+
+		assert!(lsp.failed.contains(LANG));
+
+		// what was held back is dropped, and it was never initialized so it only gets exit
+		assert_eq!(remote.recv()["method"], "exit");
+		assert_eq!(read_msg(&mut remote.r), Ok(None));
+
+	}
+
+	#[test]
+	fn shutdown() {
+
+		let (mut lsp, mut remote) = connect();
+
+		remote.initialize(json!({}));
+
+		let remote = std::thread::spawn(move || {
+			assert_eq!(remote.recv()["method"], "initialized");
+			let shutdown = remote.recv();
+			assert_eq!(shutdown["method"], "shutdown");
+			remote.respond(&shutdown, Value::Null);
+			assert_eq!(remote.recv()["method"], "exit");
+		});
+
+		wait_initialized(&mut lsp);
+		drop(lsp);
+		remote.join().unwrap();
+
+	}
+
+	#[test]
+	fn diagnostics() {
+
+		let (mut lsp, mut remote) = connect();
+		let path = test_file("diag name.rs");
+
+		remote.initialize(json!({}));
+		wait_initialized(&mut lsp);
+
+		let publish = |remote: &mut Remote, msg: &str| {
+			remote.send(json!({
+				"jsonrpc": "2.0",
+				"method": "textDocument/publishDiagnostics",
+				"params": {
+					// encoded differently from how the client does it
+					"uri": path_to_uri(&path).replace("%20", " "),
+					"diagnostics": [{
+						"range": {
+							"start": {
+								"line": 0,
+								"character": 3,
+							},
+							"end": {
+								"line": 0,
+								"character": 7,
+							},
+						},
+						"severity": 2,
+						"message": msg,
+					}],
+				},
+			}));
+		};
+
+		publish(&mut remote, "first");
+		publish(&mut remote, "second");
+
+		// wait for both
+		std::thread::sleep(Duration::from_millis(100));
+
+		assert_eq!(wait_events(&mut lsp, &path), vec![LspEvent::Diagnostics(vec![Diagnostic {
+			range: range(0, 3, 0, 7),
+			severity: Severity::Warning,
+			message: String::from("second"),
+		}])]);
+
+	}
+
+	#[test]
+	fn responses() {
+
+		let (mut lsp, mut remote) = connect();
+		let path = test_file("resp.rs");
+		let uri = path_to_uri(&path);
+		let pos = Pos {
+			line: 0,
+			character: 3,
+		};
+
+		remote.initialize(json!({}));
+		wait_initialized(&mut lsp);
+		remote.recv();
+
+		lsp.request(LANG, &path, Req::Completion, pos, json!({})).unwrap();
+
+		let req = remote.recv();
+
+		assert_eq!(req["method"], "textDocument/completion");
+		assert_eq!(req["params"]["position"], json!({ "line": 0, "character": 3 }));
+
+		remote.respond(&req, json!({
+			"isIncomplete": false,
+			"items": [
+				{
+					"label": "foo",
+					"insertText": "foo(${1:x})$0",
+					"insertTextFormat": 2,
+				},
+				{
+					"label": "bar",
+					"detail": "u8",
+					"textEdit": {
+						"range": {
+							"start": { "line": 0, "character": 0 },
+							"end": { "line": 0, "character": 3 },
+						},
+						"newText": "bar2",
+					},
+				},
+			],
+		}));
+
+		assert_eq!(wait_events(&mut lsp, &path), vec![LspEvent::Completion(vec![
+			CompletionItem {
+				label: String::from("foo"),
+				detail: None,
+				insert: String::from("foo(x)"),
+				range: None,
+			},
+			CompletionItem {
+				label: String::from("bar"),
+				detail: Some(String::from("u8")),
+				insert: String::from("bar2"),
+				range: Some(range(0, 0, 0, 3)),
+			},
+		])]);
+
+		lsp.request(LANG, &path, Req::Hover, pos, json!({})).unwrap();
+
+		let req = remote.recv();
+
+		remote.respond(&req, json!({
+			"contents": [
+				{
+					"language": "rust",
+					"value": "fn main()",
+				},
+				"docs",
+			],
+		}));
+
+		assert_eq!(wait_events(&mut lsp, &path), vec![LspEvent::Hover(String::from("fn main()\n\ndocs"))]);
+
+		lsp.request(LANG, &path, Req::Definition, pos, json!({})).unwrap();
+
+		let req = remote.recv();
+
+		// a LocationLink
+		remote.respond(&req, json!([{
+			"targetUri": uri,
+			"targetRange": {
+				"start": { "line": 0, "character": 0 },
+				"end": { "line": 2, "character": 1 },
+			},
+			"targetSelectionRange": {
+				"start": { "line": 0, "character": 3 },
+				"end": { "line": 0, "character": 7 },
+			},
+		}]));
+
+		assert_eq!(wait_events(&mut lsp, &path), vec![LspEvent::Definition(vec![Location {
+			path: path.clone(),
+			range: range(0, 3, 0, 7),
+		}])]);
+
+	}
+
+	#[test]
+	fn rename() {
+
+		let (mut lsp, mut remote) = connect();
+		let path = test_file("rename.rs");
+		let other = test_file("other.rs");
+		let edit = |text: &str| {
+			return json!([{
+				"range": {
+					"start": { "line": 0, "character": 0 },
+					"end": { "line": 0, "character": 3 },
+				},
+				"newText": text,
+			}]);
+		};
+
+		remote.initialize(json!({}));
+		wait_initialized(&mut lsp);
+		remote.recv();
+
+		lsp.request(LANG, &path, Req::Rename, Pos {
+			line: 0,
+			character: 0,
+		}, json!({
+			"newName": "bar",
+		})).unwrap();
+
+		let req = remote.recv();
+
+		assert_eq!(req["params"]["newName"], "bar");
+
+		remote.respond(&req, json!({
+			"documentChanges": [
+				{
+					"textDocument": {
+						"uri": path_to_uri(&path),
+						"version": 1,
+					},
+					"edits": edit("bar"),
+				},
+				{
+					"textDocument": {
+						"uri": path_to_uri(&other),
+						"version": null,
+					},
+					"edits": edit("baz"),
+				},
+			],
+		}));
+
+		let events = wait_events(&mut lsp, &path);
+		let text_edit = |text: &str| {
+			return LspEvent::Edits(vec![TextEdit {
+				range: range(0, 0, 0, 3),
+				text: String::from(text),
+			}]);
+		};
+
+		assert_eq!(events, vec![text_edit("bar"), LspEvent::Renamed(vec![other.clone()])]);
+		assert_eq!(lsp.take_events(&other), vec![text_edit("baz")]);
+		assert_eq!(lsp.take_edited(), vec![path, other]);
+
+	}
+
+}
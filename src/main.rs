@@ -5,16 +5,20 @@ mod bufs;
 mod term;
 mod session;
 mod conf;
+mod lsp;
 
 use browser::*;
 use bufs::*;
 use term::*;
 use session::*;
 use conf::*;
+use lsp::*;
 
 use std::mem;
 use std::path::Path;
 use std::path::PathBuf;
+use std::rc::Rc;
+use std::cell::RefCell;
 use std::time::Instant;
 use std::time::Duration;
 use std::collections::VecDeque;
@@ -42,6 +46,8 @@ const BUFBAR_HEIGHT: f32 = BUFBAR_FONT_SIZE + BUFBAR_PADDING.y * 2.0;
 
 const LOG_SIZE: usize = 5;
 const LOG_LIFE: f32 = 4.0;
+// ms of no typing before completing on its own
+const COMPLETE_DELAY: u64 = 300;
 
 #[derive(Clone, Copy, Debug, PartialEq)]
 enum View {
@@ -87,9 +93,23 @@ trait Buffer: 'static {
 	fn log(&mut self) -> Option<&mut Vec<Msg>> {
 		return None;
 	}
+	fn actions(&mut self) -> Option<&mut Vec<Action>> {
+		return None;
+	}
+	// for buffers that aren't the current one, which don't get updates
+	fn handle_lsp(&mut self) {}
 
 }
 
+/// Something a buffer asks the app to do.
+#[derive(Clone, Debug, PartialEq)]
+enum Action {
+	Open(PathBuf),
+	// a buffer with some text that's not a file, and where it'd be saved
+	Scratch(PathBuf, String),
+	Close,
+}
+
 #[derive(Clone, Debug, PartialEq)]
 struct Msg {
 	r#type: MsgType,
@@ -142,6 +162,7 @@ struct App {
 	bufbar_offset: f32,
 	bookmarks: Vec<PathBuf>,
 	log: VecDeque<Msg>,
+	shared: Shared,
 }
 
 impl App {
@@ -267,7 +288,13 @@ impl App {
 		}
 	}
 
-	fn new_buf(&mut self, mut b: impl Buffer) {
+	fn new_buf(&mut self, b: impl Buffer) {
+		let id = self.add_buf(b);
+		self.to_buf(id);
+	}
+
+	// open a buffer without going to it
+	fn add_buf(&mut self, mut b: impl Buffer) -> ID {
 
 		let id = self.last_buf_id;
 
@@ -275,7 +302,38 @@ impl App {
 
 		self.buffers.insert(id, Box::new(b));
 		self.last_buf_id += 1;
-		self.to_buf(id);
+
+		return id;
+
+	}
+
+	// put what a server left for a file in its buffer now, opening one if there isn't
+	fn apply_lsp_edits(&mut self, path: &Path) {
+
+		let buf = self.buffers
+			.values_mut()
+			.find(|b| b.path().and_then(|p| p.canonicalize().ok()).as_deref() == Some(path));
+
+		if let Some(buf) = buf {
+			buf.handle_lsp();
+			return;
+		}
+
+		let buf = std::fs::read(path)
+			.ok()
+			.filter(|bytes| !is_binary(bytes))
+			.and_then(|_| TextEditor::new(path, &self.shared).ok());
+
+		match buf {
+			Some(mut buf) => {
+				buf.handle_lsp();
+				self.add_buf(buf);
+			},
+			None => {
+				self.shared.lsp.borrow_mut().take_events(path);
+				self.log.push_back(Msg::error(&format!("failed to apply edits to {}", path.display())));
+			},
+		}
 
 	}
 
@@ -320,7 +378,16 @@ impl App {
 
 		}
 
-		self.new_buf(TextEditor::new(path));
+		if let Ok(bytes) = std::fs::read(path) {
+			if is_binary(&bytes) {
+				return Ok(self.new_buf(HexViewer::new(path, bytes)));
+			}
+		}
+
+		match TextEditor::new(path, &self.shared) {
+			Ok(buf) => self.new_buf(buf),
+			Err(e) => self.log.push_back(Msg::error(&e)),
+		}
 
 		return Ok(());
 
@@ -337,7 +404,30 @@ impl App {
 			}
 		}
 
-		self.new_buf(TextEditor::new(self.browser.path().join(fname)));
+		match TextEditor::new(path, &self.shared) {
+			Ok(buf) => self.new_buf(buf),
+			Err(e) => self.log.push_back(Msg::error(&e)),
+		}
+
+	}
+
+	// what the current buffer asked for
+	fn run_actions(&mut self, d: &mut Ctx) -> Result<()> {
+
+		let actions = self.cur_buf_mut()
+			.and_then(|b| b.actions())
+			.map(|a| mem::replace(a, vec![]))
+			.unwrap_or(vec![]);
+
+		for a in actions {
+			match a {
+				Action::Open(path) => self.open(d, path)?,
+				Action::Scratch(path, content) => self.new_buf(TextEditor::scratch(path, &content, &self.shared)),
+				Action::Close => self.close_cur_buf(),
+			}
+		}
+
+		return Ok(());
 
 	}
 
@@ -371,6 +461,12 @@ impl State for App {
 		});
 
 		let conf = Conf::load().unwrap_or_default();
+		let langs = conf.lang_props();
+		let servers = conf.servers.clone().unwrap_or_default();
+		let complete_delay = match conf.complete_delay.unwrap_or(COMPLETE_DELAY) {
+			0 => None,
+			ms => Some(Duration::from_millis(ms)),
+		};
 
 		let bookmarks = conf.bookmarks
 			.unwrap_or_else(|| vec![])
@@ -378,6 +474,20 @@ impl State for App {
 			.map(|b| expand_path(b))
 			.collect();
 
+		// files might've been deleted or changed since, and histories for them pile up
+		std::thread::spawn(prune_undo);
+
+		let registers = Registers::new(conf.clipboard.unwrap_or(true));
+		let mut log = vecd![];
+
+		let snippets = match Snippets::load() {
+			Ok(snippets) => snippets.unwrap_or_default(),
+			Err(e) => {
+				log.push_back(Msg::error(&e));
+				Snippets::default()
+			},
+		};
+
 		let mut app = Self {
 			bookmarks: bookmarks,
 			browser: FileBrowser::new(session.path)?,
@@ -387,13 +497,28 @@ impl State for App {
 			last_buf_id: 0,
 			cur_buf: None,
 			bufbar_offset: 0.0,
-			log: vecd![],
+			log: log,
+			shared: Shared {
+				registers: Rc::new(RefCell::new(registers)),
+				macros: Rc::new(RefCell::new(Macros::load())),
+				langs: Rc::new(langs),
+				backup: conf.backup.unwrap_or(false),
+				lsp: Rc::new(RefCell::new(Lsp::new(servers))),
+				words: Rc::new(RefCell::new(WordIndex::new())),
+				complete_delay: complete_delay,
+				snippets: Rc::new(snippets),
+			},
 		};
 
 		for path in session.bufs {
 			app.open(d, path)?;
 		}
 
+		// left by a crash, they ask to be recovered when opened
+		for path in swapped_files() {
+			app.open(d, path)?;
+		}
+
 		app.view = View::Browser;
 
 		return Ok(app);
@@ -409,6 +534,7 @@ impl State for App {
 				if let Some(buf) = self.cur_buf_mut() {
 					buf.event(d, e)?;
 				}
+				self.run_actions(d)?;
 				match e {
 					Event::KeyPress(k) => {
 						match k {
@@ -519,12 +645,23 @@ impl State for App {
 				if let Some(buf) = self.cur_buf_mut() {
 					buf.update(d)?;
 				}
+				// some come in later, like where a definition is
+				self.run_actions(d)?;
 			},
 			View::Browser => self.browser.update(d)?,
 			View::Term => self.term.update(d)?,
 		}
 
+		self.shared.lsp.borrow_mut().poll();
+
+		// a rename goes in every file it touched at once
+		let edited = self.shared.lsp.borrow_mut().take_edited();
+
+		for path in edited {
+			self.apply_lsp_edits(&path);
+		}
 		self.log.extend(mem::replace(self.browser.log(), vec![]));
+		self.log.extend(mem::replace(self.shared.lsp.borrow_mut().log(), vec![]));
 
 		for b in self.buffers.values_mut() {
 			if let Some(log) = b.log() {
@@ -681,6 +818,10 @@ impl State for App {
 
 	fn quit(&mut self, _: &mut Ctx) -> Result<()> {
 
+		for buf in self.buffers.values_mut() {
+			buf.close();
+		}
+
 		let session = Session {
 			path: self.browser
 				.path()
@@ -737,6 +878,70 @@ fn expand_path(path: &str) -> PathBuf {
 
 }
 
+/// A directory under the app's data dir, created if it isn't there.
+fn data_dir(name: &str) -> Result<PathBuf> {
+
+	let dir = dirs_next::data_dir()
+		.ok_or_else(|| format!("failed to get data dir"))?
+		.join(PROJ)
+		.join(name);
+
+	std::fs::create_dir_all(&dir)
+		.map_err(|_| format!("failed to create {}", dir.display()))?;
+
+	return Ok(dir);
+
+}
+
+/// Write a file through a temporary file next to it that's moved over it, so a failed write never leaves it half written.
+///
+/// Permissions and, where allowed, the owner are kept, and symlinks are written through. If there's no creating files next to it, it's written in place.
+fn write_atomic(path: impl AsRef<Path>, bytes: &[u8]) -> Result<()> {
+
+	use std::io::Write;
+
+	let path = path.as_ref();
+	let path = std::fs::canonicalize(path).unwrap_or(path.to_path_buf());
+
+	let name = path
+		.file_name()
+		.map(|n| n.to_string_lossy().to_string())
+		.ok_or_else(|| format!("invalid path {}", path.display()))?;
+
+	let tmp = path.with_file_name(format!(".{}.{}.tmp", name, std::process::id()));
+
+	// like a read only directory with a writable file in it
+	let mut file = match std::fs::File::create(&tmp) {
+		Ok(file) => file,
+		Err(_) => {
+			return std::fs::write(&path, bytes)
+				.map_err(|_| format!("failed to write to {}", path.display()));
+		},
+	};
+
+	let mut write = || -> std::io::Result<()> {
+		file.write_all(bytes)?;
+		file.sync_all()?;
+		if let Ok(meta) = std::fs::metadata(&path) {
+			std::fs::set_permissions(&tmp, meta.permissions())?;
+			// only root can give files away, otherwise it's the one writing's
+			#[cfg(unix)] {
+				use std::os::unix::fs::MetadataExt;
+				std::os::unix::fs::chown(&tmp, Some(meta.uid()), Some(meta.gid())).ok();
+			}
+		}
+		return std::fs::rename(&tmp, &path);
+	};
+
+	if write().is_err() {
+		std::fs::remove_file(&tmp).ok();
+		return Err(format!("failed to write to {}", path.display()));
+	}
+
+	return Ok(());
+
+}
+
 fn main() {
 
 	if let Err(e) = launcher()
@@ -6,7 +6,7 @@ use serde::Deserialize;
 
 use crate::*;
 
-const PROJ: &str = "space55";
+pub const PROJ: &str = "space55";
 const ENTRY: &str = "session";
 
 #[derive(Serialize, Deserialize, Clone, Debug)]